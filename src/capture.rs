@@ -0,0 +1,165 @@
+//! Writes rendered frames out for encoding videos later, as numbered PNGs or a raw Y4M stream.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+enum Writer {
+    // Directory the numbered PNGs go to.
+    Png(PathBuf),
+    // Stream and the frame size its header was written for, on the first frame.
+    Y4m(BufWriter<File>, Option<(u16, u16)>),
+}
+
+/// Frames of a video playing at a fixed frame rate, whatever time they take to render.
+pub struct Capture {
+    path: String,
+    writer: Writer,
+    fps: u32,
+    frames: usize,
+}
+
+impl Capture {
+    /// Writes a Y4M stream when `path` ends with `.y4m`, and `000000.png`, `000001.png`, and so
+    /// on into the `path` directory otherwise, creating it.
+    pub fn create(path: &str, fps: u32) -> Result<Capture, String> {
+        if fps == 0 {
+            return Err("capture needs a frame rate above 0".to_string());
+        }
+        let writer = if path.ends_with(".y4m") {
+            let file =
+                File::create(path).map_err(|err| format!("can't create '{}': {}", path, err))?;
+            Writer::Y4m(BufWriter::new(file), None)
+        } else {
+            std::fs::create_dir_all(path)
+                .map_err(|err| format!("can't create '{}': {}", path, err))?;
+            Writer::Png(PathBuf::from(path))
+        };
+        Ok(Capture {
+            path: path.to_string(),
+            writer,
+            fps,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Seconds of the video every frame takes.
+    pub fn frame_time(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    /// Y4M streams can't change their size, frames of another size than the first one are
+    /// errors.
+    pub fn write_frame(
+        &mut self,
+        buffer: &[[u8; 4]],
+        width: u16,
+        height: u16,
+    ) -> Result<(), String> {
+        let path = &self.path;
+        let write_error = |err: std::io::Error| format!("can't write '{}': {}", path, err);
+        match &mut self.writer {
+            Writer::Png(directory) => {
+                let file = directory.join(format!("{:06}.png", self.frames));
+                image::save_buffer(
+                    &file,
+                    &buffer.concat(),
+                    width as u32,
+                    height as u32,
+                    image::ColorType::Rgba8,
+                )
+                .map_err(|err| format!("can't write '{}': {}", file.display(), err))?;
+            }
+            Writer::Y4m(stream, size) => {
+                match size {
+                    Some(size) if *size != (width, height) => {
+                        return Err(format!(
+                            "can't write a {}x{} frame into the {}x{} stream '{}'",
+                            width, height, size.0, size.1, path
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        *size = Some((width, height));
+                        writeln!(
+                            stream,
+                            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                            width, height, self.fps
+                        )
+                        .map_err(write_error)?;
+                    }
+                }
+                stream.write_all(b"FRAME\n").map_err(write_error)?;
+                for plane in ycbcr_planes(buffer) {
+                    stream.write_all(&plane).map_err(write_error)?;
+                }
+                stream.flush().map_err(write_error)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Full resolution luma and chroma planes, in the limited range BT.601 Y4M readers assume.
+fn ycbcr_planes(buffer: &[[u8; 4]]) -> [Vec<u8>; 3] {
+    let mut planes = [
+        Vec::with_capacity(buffer.len()),
+        Vec::with_capacity(buffer.len()),
+        Vec::with_capacity(buffer.len()),
+    ];
+    for pixel in buffer {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        let y = 16.0 + 0.2568 * r + 0.5041 * g + 0.0979 * b;
+        let cb = 128.0 - 0.1482 * r - 0.2910 * g + 0.4392 * b;
+        let cr = 128.0 + 0.4392 * r - 0.3678 * g - 0.0714 * b;
+        for (plane, value) in planes.iter_mut().zip([y, cb, cr]) {
+            plane.push(value.round() as u8);
+        }
+    }
+    planes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn y4m_streams_have_a_header_and_a_plane_per_channel() {
+        let path = std::env::temp_dir().join(format!("ray-ten-capture-{}.y4m", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (width, height) = (3, 2);
+        let buffer = vec![[255, 0, 0, 255]; width as usize * height as usize];
+        let mut capture = Capture::create(path, 30).unwrap();
+        capture.write_frame(&buffer, width, height).unwrap();
+        capture.write_frame(&buffer, width, height).unwrap();
+        assert!(capture.write_frame(&buffer, height, width).is_err());
+        drop(capture);
+        let bytes = std::fs::read(path);
+        std::fs::remove_file(path).unwrap();
+        let bytes = bytes.unwrap();
+
+        let header = b"YUV4MPEG2 W3 H2 F30:1 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        let frame_size = b"FRAME\n".len() + 3 * buffer.len();
+        assert_eq!(bytes.len(), header.len() + 2 * frame_size);
+        for frame in bytes[header.len()..].chunks(frame_size) {
+            assert!(frame.starts_with(b"FRAME\n"));
+            // Pure red in limited range BT.601.
+            let planes = &frame[b"FRAME\n".len()..];
+            assert!(planes[..buffer.len()].iter().all(|&y| y == 81));
+            assert!(planes[buffer.len()..2 * buffer.len()]
+                .iter()
+                .all(|&cb| cb == 90));
+            assert!(planes[2 * buffer.len()..].iter().all(|&cr| cr == 240));
+        }
+    }
+}
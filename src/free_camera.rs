@@ -0,0 +1,79 @@
+use macroquad::prelude::*;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::math::{Axis, Matrix, Real, Vector};
+use crate::render::camera::Camera;
+
+const FLY_SPEED: Real = 4.0;
+const LOOK_SENSITIVITY: Real = 0.005;
+const ZOOM_SENSITIVITY: Real = 0.05;
+const MIN_FOV: Real = 0.2;
+const MAX_FOV: Real = 2.8;
+
+/// Debug camera detached from the near paddle, flown with WASD, Q/E and the right mouse button.
+pub struct FreeCamera {
+    yaw: Real,
+    pitch: Real,
+    fov: Real,
+    simulation_paused: bool,
+    prev_mouse_pos: (f32, f32),
+}
+
+impl FreeCamera {
+    pub fn new(fov: Real) -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.0,
+            fov,
+            simulation_paused: false,
+            prev_mouse_pos: mouse_position(),
+        }
+    }
+
+    pub fn simulation_paused(&self) -> bool {
+        self.simulation_paused
+    }
+
+    pub fn control(&mut self, camera: &mut Camera, elapsed: Real) {
+        if is_key_pressed(KeyCode::P) {
+            self.simulation_paused = !self.simulation_paused;
+        }
+
+        let mouse_pos = mouse_position();
+        if is_mouse_button_down(MouseButton::Right) {
+            self.yaw -= (mouse_pos.0 - self.prev_mouse_pos.0) * LOOK_SENSITIVITY;
+            self.pitch -= (mouse_pos.1 - self.prev_mouse_pos.1) * LOOK_SENSITIVITY;
+            self.pitch = self.pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+        }
+        self.prev_mouse_pos = mouse_pos;
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 {
+            self.fov = (self.fov - wheel.signum() * ZOOM_SENSITIVITY).clamp(MIN_FOV, MAX_FOV);
+        }
+
+        let orientation =
+            Matrix::rotation(Axis::YS, self.yaw) * Matrix::rotation(Axis::XS, self.pitch);
+        let forward = Vector::new(0.0, 0.0, 0.0) - orientation.column(Axis::ZS);
+        let right = orientation.column(Axis::XS);
+        let up = Vector::new(0.0, 1.0, 0.0);
+
+        let mut shift = Vector::new(0.0, 0.0, 0.0);
+        for (key, dir) in [
+            (KeyCode::W, forward),
+            (KeyCode::S, Vector::new(0.0, 0.0, 0.0) - forward),
+            (KeyCode::D, right),
+            (KeyCode::A, Vector::new(0.0, 0.0, 0.0) - right),
+            (KeyCode::E, up),
+            (KeyCode::Q, Vector::new(0.0, 0.0, 0.0) - up),
+        ] {
+            if is_key_down(key) {
+                shift = shift + dir;
+            }
+        }
+
+        camera.move_to(camera.origin() + shift * (FLY_SPEED * elapsed));
+        camera.set_orientation(orientation);
+        camera.set_fov(self.fov);
+    }
+}
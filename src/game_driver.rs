@@ -1,87 +1,33 @@
 use macroquad::prelude::*;
 
-use crate::math::{Directions, Real};
-use crate::motion::{MotionResult, MotionTicker};
-use crate::render::{camera::Camera, renderer::Renderer};
-use crate::scene::{Obstacle, Plane, Scene, Sphere};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::capture::Capture;
+use crate::free_camera::FreeCamera;
+use crate::game_state::{control_far_paddle, AdvanceResult, GameState, FIELD_OF_VIEW};
+use crate::math::{Directions, Point};
+use crate::render::crt::CrtFilter;
+use crate::render::post::ToneMapping;
+use crate::render::renderer::{Interlacing, Stereo};
+use crate::render::spectrum::{Palette, SPECTRUM_RESOLUTION};
+use crate::render::stats::{FrameStats, FrameTimes};
+use crate::render::tracer::RenderMode;
+use crate::scene::Sphere;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::session::{SessionRecorder, Step};
 use crate::ui;
 
-const MAX_DEPTH: usize = 5;
-#[cfg(not(target_arch = "wasm32"))]
-const SAMPLES_PER_PIXEL: usize = 2;
-#[cfg(target_arch = "wasm32")]
-const SAMPLES_PER_PIXEL: usize = 1;
 const MENU_CHANGE_TIMEOUT: f32 = 0.2;
 const COLLISION_NOTICE_TIMEOUT: f32 = 1.0;
-
-struct GameState {
-    camera: Camera,
-    scene: Scene,
-    motion_ticker: MotionTicker,
-    renderer: Renderer,
-}
-
-#[derive(Clone, Copy)]
-enum AdvanceResult {
-    PlayerHit,
-    AIHit,
-    PlayerMiss,
-    AIMiss,
-    None,
-}
-
-impl GameState {
-    fn new(width: u16, height: u16) -> Self {
-        Self {
-            camera: Camera::new(width as f32 / height as f32, 2.0f32),
-            scene: Scene::new(),
-            motion_ticker: MotionTicker::new(),
-            renderer: Renderer::new((width, height), SAMPLES_PER_PIXEL, MAX_DEPTH),
-        }
-    }
-
-    fn advance(
-        &mut self,
-        elapsed: f32,
-        near_paddle_directions: Directions,
-        far_paddle_directions: Directions,
-    ) -> AdvanceResult {
-        let motion_result = self.motion_ticker.tick(
-            &mut self.scene,
-            elapsed,
-            near_paddle_directions,
-            far_paddle_directions,
-        );
-        let near_paddle_pos = self.scene.sphere_pos(Sphere::NearPaddle);
-        self.camera
-            .move_origin_to(near_paddle_pos.x(), near_paddle_pos.y());
-
-        match motion_result {
-            MotionResult::Colision(Obstacle::Plane(Plane::Near)) => AdvanceResult::PlayerMiss,
-            MotionResult::Colision(Obstacle::Plane(Plane::Far)) => AdvanceResult::AIMiss,
-            MotionResult::Colision(Obstacle::Sphere(Sphere::NearPaddle)) => {
-                AdvanceResult::PlayerHit
-            }
-            MotionResult::Colision(Obstacle::Sphere(Sphere::FarPaddle)) => AdvanceResult::AIHit,
-            _ => AdvanceResult::None,
-        }
-    }
-
-    fn render(&self, coef: f32, image: &mut Image) {
-        self.renderer
-            .render(&self.scene, &self.camera, coef, image.get_image_data_mut());
-    }
-}
-
-pub fn control_far_paddle(scene: &Scene) -> Directions {
-    const EPSILON: Real = 0.1;
-    Directions::new(
-        (scene.sphere_pos(Sphere::Ball).y() - scene.sphere_pos(Sphere::FarPaddle).y()) > EPSILON,
-        (scene.sphere_pos(Sphere::FarPaddle).y() - scene.sphere_pos(Sphere::Ball).y()) > EPSILON,
-        (scene.sphere_pos(Sphere::FarPaddle).x() - scene.sphere_pos(Sphere::Ball).x()) > EPSILON,
-        (scene.sphere_pos(Sphere::Ball).x() - scene.sphere_pos(Sphere::FarPaddle).x()) > EPSILON,
-    )
-}
+const ATTRACT_MODE_APERTURE: f32 = 0.15;
+// Page up and down change the exposure by this many stops.
+const EXPOSURE_STEP: f32 = 0.5;
+#[cfg(not(target_arch = "wasm32"))]
+const STATS_CSV_PATH: &str = "ray-ten-stats.csv";
+#[cfg(not(target_arch = "wasm32"))]
+const CAPTURE_PATH: &str = "ray-ten-capture.y4m";
+// Captured games advance this many steps per second, however long frames take to render.
+#[cfg(not(target_arch = "wasm32"))]
+const CAPTURE_FPS: u32 = 30;
 
 #[derive(Clone, Copy, PartialEq)]
 enum Action {
@@ -91,17 +37,45 @@ enum Action {
     EndGame,
     Continue,
     MainMenu,
+    Settings,
+    ToggleCrt,
+    NextPalette,
+    NextStereo,
+    ToggleSpectrumResolution,
+    ToggleWallMarkers,
+    ToggleInterceptGhost,
+    ToggleDepthRing,
+    #[cfg(not(target_arch = "wasm32"))]
+    ToggleRecording,
+    Back,
 }
 
-const MENU_ITEMS: [&[(&str, Action)]; 4] = [
-    &[("New game", Action::NewGame), ("Exit", Action::Exit)],
+const MENU_ITEMS: [&[(&str, Action)]; 5] = [
+    &[
+        ("New game", Action::NewGame),
+        ("Settings", Action::Settings),
+        ("Exit", Action::Exit),
+    ],
     &[],
     &[
         ("Continue", Action::Continue),
+        ("Settings", Action::Settings),
         ("Main menu", Action::EndGame),
         ("Exit", Action::Exit),
     ],
     &[("Continue", Action::MainMenu)],
+    &[
+        ("CRT filter", Action::ToggleCrt),
+        ("Palette", Action::NextPalette),
+        ("Stereo 3D", Action::NextStereo),
+        ("Spectrum resolution", Action::ToggleSpectrumResolution),
+        ("Wall markers", Action::ToggleWallMarkers),
+        ("Intercept ghost", Action::ToggleInterceptGhost),
+        ("Depth ring", Action::ToggleDepthRing),
+        #[cfg(not(target_arch = "wasm32"))]
+        ("Record games", Action::ToggleRecording),
+        ("Back", Action::Back),
+    ],
 ];
 
 #[derive(Clone, Copy, PartialEq)]
@@ -110,6 +84,8 @@ enum UIState {
     Hud,
     PauseMenu,
     EndGame,
+    // Opened from the menu in `settings_parent`, going back to it.
+    Settings,
 }
 
 impl UIState {
@@ -130,6 +106,7 @@ enum TouchDirection {
 pub struct GameDriver {
     width: u16,
     height: u16,
+    max_pixels: f32,
     game_state: GameState,
     ui_state: UIState,
     current_selected_item: usize,
@@ -141,6 +118,24 @@ pub struct GameDriver {
     texture: Texture2D,
     current_hovered_item: Option<usize>,
     prev_mouse_pos: (f32, f32),
+    free_camera: Option<FreeCamera>,
+    show_stats: bool,
+    // Renders at the Spectrum's resolution, letterboxed, rather than the window's.
+    spectrum_resolution: bool,
+    // Filters rendered frames to look like on a CRT when set.
+    crt_filter: Option<CrtFilter>,
+    settings_parent: UIState,
+    frame_times: FrameTimes,
+    frames_drawn: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    stats_csv: Option<std::fs::File>,
+    #[cfg(not(target_arch = "wasm32"))]
+    capture: Option<Capture>,
+    // Records every new game into its own session file when set.
+    #[cfg(not(target_arch = "wasm32"))]
+    record_games: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    session: Option<SessionRecorder>,
 }
 
 impl GameDriver {
@@ -151,6 +146,7 @@ impl GameDriver {
         GameDriver {
             width,
             height,
+            max_pixels: width as f32 * height as f32,
             game_state: GameState::new(width, height),
             ui_state: UIState::MainMenu,
             current_selected_item: 0,
@@ -162,10 +158,26 @@ impl GameDriver {
             texture,
             current_hovered_item: None,
             prev_mouse_pos: (0.0, 0.0),
+            free_camera: None,
+            show_stats: false,
+            spectrum_resolution: false,
+            crt_filter: None,
+            settings_parent: UIState::MainMenu,
+            frame_times: FrameTimes::default(),
+            frames_drawn: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            stats_csv: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            capture: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            record_games: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            session: None,
         }
     }
 
     pub fn next_frame(&mut self) -> bool {
+        self.fit_to_screen();
         if let Some(action) = self.process_inputs() {
             if let Action::Exit = action {
                 return false;
@@ -177,10 +189,116 @@ impl GameDriver {
         true
     }
 
+    /// Re-creates the render target when the window changes its shape, never rendering more
+    /// pixels than the initial size.
+    fn fit_to_screen(&mut self) {
+        let (screen_width, screen_height) = (screen_width(), screen_height());
+        // Minimized windows have no size to fit.
+        if screen_width < 1.0 || screen_height < 1.0 {
+            return;
+        }
+        let (width, height) = if self.spectrum_resolution {
+            SPECTRUM_RESOLUTION
+        } else {
+            let scale = (self.max_pixels / (screen_width * screen_height))
+                .sqrt()
+                .min(1.0);
+            (
+                ((screen_width * scale).round() as u16).max(1),
+                ((screen_height * scale).round() as u16).max(1),
+            )
+        };
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.image = Image::gen_image_color(width, height, WHITE);
+        self.texture.delete();
+        self.texture = Texture2D::from_image(&self.image);
+        self.texture.set_filter(FilterMode::Nearest);
+        self.game_state.resize(width, height);
+    }
+
     fn process_inputs(&mut self) -> Option<Action> {
         self.since_last_selection_change += get_frame_time();
         self.since_last_collision += get_frame_time();
 
+        if is_key_pressed(KeyCode::F2) {
+            let mode = self.game_state.renderer.mode().next();
+            self.game_state.renderer.set_mode(mode);
+        }
+
+        if is_key_pressed(KeyCode::F5) {
+            let adaptive = !self.game_state.adaptive_sampling();
+            self.game_state.set_adaptive_sampling(adaptive);
+        }
+
+        if is_key_pressed(KeyCode::F6) {
+            let interlacing = self.game_state.renderer.interlacing().next();
+            self.game_state.renderer.set_interlacing(interlacing);
+        }
+
+        if is_key_pressed(KeyCode::F7) {
+            let post_processing = !self.game_state.post_processing();
+            self.game_state.set_post_processing(post_processing);
+        }
+
+        if is_key_pressed(KeyCode::F8) {
+            let tone_mapping = self.game_state.renderer.tone_mapping().next();
+            self.game_state.renderer.set_tone_mapping(tone_mapping);
+        }
+
+        for (key, step) in [
+            (KeyCode::PageUp, EXPOSURE_STEP),
+            (KeyCode::PageDown, -EXPOSURE_STEP),
+        ] {
+            if is_key_pressed(key) {
+                let exposure = self.game_state.renderer.exposure() + step;
+                self.game_state.renderer.set_exposure(exposure);
+            }
+        }
+
+        if is_key_pressed(KeyCode::F9) {
+            let palette = self.game_state.renderer.palette().next();
+            self.game_state.renderer.set_palette(palette);
+        }
+
+        if is_key_pressed(KeyCode::F10) {
+            self.spectrum_resolution = !self.spectrum_resolution;
+        }
+
+        if is_key_pressed(KeyCode::F11) {
+            self.toggle_crt_filter();
+        }
+
+        if is_key_pressed(KeyCode::F3) {
+            self.show_stats = !self.show_stats;
+            self.update_stats_collection();
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if is_key_pressed(KeyCode::F4) {
+            self.toggle_stats_csv();
+            self.update_stats_collection();
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if is_key_pressed(KeyCode::F12) {
+            self.toggle_capture();
+        }
+
+        if is_key_pressed(KeyCode::F1) {
+            if self.free_camera.take().is_some() {
+                self.game_state
+                    .attach_camera(self.width as f32 / self.height as f32);
+            } else {
+                self.free_camera = Some(FreeCamera::new(FIELD_OF_VIEW));
+                self.game_state.detach_camera();
+            }
+        }
+
         if self.since_last_selection_change < MENU_CHANGE_TIMEOUT {
             return None;
         }
@@ -193,7 +311,7 @@ impl GameDriver {
                     return Some(Action::Pause);
                 }
             }
-            UIState::MainMenu | UIState::PauseMenu | UIState::EndGame => {
+            UIState::MainMenu | UIState::PauseMenu | UIState::EndGame | UIState::Settings => {
                 if is_key_down(KeyCode::Enter) {
                     self.since_last_selection_change = 0.0;
                     return Some(self.ui_state.menu_items()[self.current_selected_item].1);
@@ -209,6 +327,10 @@ impl GameDriver {
                     self.since_last_selection_change = 0.0;
                     return Some(Action::Continue);
                 }
+                if is_key_down(KeyCode::Escape) && self.ui_state == UIState::Settings {
+                    self.since_last_selection_change = 0.0;
+                    return Some(Action::Back);
+                }
                 if is_key_down(KeyCode::Up) {
                     self.current_selected_item =
                         (self.current_selected_item - 1) % self.ui_state.menu_items().len();
@@ -227,7 +349,9 @@ impl GameDriver {
     fn process_action(&mut self, action: Action) {
         match action {
             Action::NewGame => {
-                self.game_state = GameState::new(self.width, self.height);
+                self.game_state.restart();
+                #[cfg(not(target_arch = "wasm32"))]
+                self.start_session();
                 self.ui_state = UIState::Hud;
                 self.score = 0;
             }
@@ -239,6 +363,10 @@ impl GameDriver {
                 self.current_hovered_item = None;
             }
             Action::EndGame => {
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    self.session = None;
+                }
                 self.ui_state = UIState::EndGame;
                 self.current_selected_item = 0;
                 self.since_last_selection_change = 0.0;
@@ -253,13 +381,82 @@ impl GameDriver {
                 self.since_last_selection_change = 0.0;
                 self.current_hovered_item = None;
             }
+            Action::Settings => {
+                self.settings_parent = self.ui_state;
+                self.ui_state = UIState::Settings;
+                self.current_selected_item = 0;
+                self.since_last_selection_change = 0.0;
+                self.current_hovered_item = None;
+            }
+            Action::ToggleCrt => {
+                self.toggle_crt_filter();
+                self.since_last_selection_change = 0.0;
+            }
+            Action::NextPalette => {
+                let palette = self.game_state.renderer.palette().next();
+                self.game_state.renderer.set_palette(palette);
+                self.since_last_selection_change = 0.0;
+            }
+            Action::NextStereo => {
+                let stereo = self.game_state.renderer.stereo().next();
+                self.game_state.renderer.set_stereo(stereo);
+                self.since_last_selection_change = 0.0;
+            }
+            Action::ToggleSpectrumResolution => {
+                self.spectrum_resolution = !self.spectrum_resolution;
+                self.since_last_selection_change = 0.0;
+            }
+            Action::ToggleWallMarkers => {
+                let wall_markers = !self.game_state.wall_markers();
+                self.game_state.set_wall_markers(wall_markers);
+                self.since_last_selection_change = 0.0;
+            }
+            Action::ToggleInterceptGhost => {
+                let intercept_ghost = !self.game_state.intercept_ghost();
+                self.game_state.set_intercept_ghost(intercept_ghost);
+                self.since_last_selection_change = 0.0;
+            }
+            Action::ToggleDepthRing => {
+                let depth_ring = !self.game_state.depth_ring();
+                self.game_state.set_depth_ring(depth_ring);
+                self.since_last_selection_change = 0.0;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Action::ToggleRecording => {
+                self.record_games = !self.record_games;
+                self.since_last_selection_change = 0.0;
+            }
+            Action::Back => {
+                self.ui_state = self.settings_parent;
+                self.current_selected_item = 0;
+                self.since_last_selection_change = 0.0;
+                self.current_hovered_item = None;
+            }
         }
     }
 
     fn advance(&mut self) {
-        if let UIState::PauseMenu = self.ui_state {
+        self.frame_times.physics = 0.0;
+        // Settings behave like the menu they were opened from.
+        let ui_state = match self.ui_state {
+            UIState::Settings => self.settings_parent,
+            ui_state => ui_state,
+        };
+        if let UIState::PauseMenu = ui_state {
             return;
         }
+        let aperture = if let UIState::MainMenu = ui_state {
+            ATTRACT_MODE_APERTURE
+        } else {
+            0.0
+        };
+        self.game_state.camera.set_aperture(aperture);
+        if let Some(free_camera) = &mut self.free_camera {
+            free_camera.control(&mut self.game_state.camera, get_frame_time());
+            if free_camera.simulation_paused() {
+                return;
+            }
+        }
         let far_directions = control_far_paddle(&self.game_state.scene);
         let near_directions = if let UIState::Hud = self.ui_state {
             Directions::new(
@@ -272,10 +469,33 @@ impl GameDriver {
             far_directions
         };
 
-        match self
+        // Captured frames are a fixed time apart for the video to play at the right speed.
+        #[cfg(not(target_arch = "wasm32"))]
+        let elapsed = match &self.capture {
+            Some(capture) => capture.frame_time(),
+            None => get_frame_time(),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let elapsed = get_frame_time();
+        let physics_start = get_time();
+        let advance_result = self
             .game_state
-            .advance(get_frame_time(), near_directions, far_directions)
-        {
+            .advance(elapsed, near_directions, far_directions);
+        self.frame_times.physics = get_time() - physics_start;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(session) = &mut self.session {
+            let step = Step {
+                elapsed,
+                near_directions,
+                far_directions,
+            };
+            if let Err(err) = session.record(step) {
+                eprintln!("{}, recording stopped", err);
+                self.session = None;
+            }
+        }
+
+        match advance_result {
             AdvanceResult::PlayerHit => {
                 self.since_last_collision = 0.0;
                 self.last_collision = true;
@@ -293,21 +513,55 @@ impl GameDriver {
         }
     }
 
+    fn render_frame(&mut self, coef: f32) {
+        let render_start = get_time();
+        self.game_state
+            .render(coef, self.image.get_image_data_mut());
+        if let Some(crt_filter) = &mut self.crt_filter {
+            crt_filter.apply(self.image.get_image_data_mut(), self.width as usize);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(capture) = &mut self.capture {
+            if let Err(err) =
+                capture.write_frame(self.image.get_image_data(), self.width, self.height)
+            {
+                eprintln!("{}, capture stopped", err);
+                self.capture = None;
+            }
+        }
+        let upload_start = get_time();
+        self.texture.update(&self.image);
+        let (mut x, mut y) = (0.0, 0.0);
+        let (mut width, mut height) = (screen_width(), screen_height());
+        if self.spectrum_resolution {
+            // Keeps the Spectrum's aspect ratio, with a border around it like on a TV.
+            let scale = (width / self.width as f32).min(height / self.height as f32);
+            x = (width - self.width as f32 * scale) / 2.0;
+            y = (height - self.height as f32 * scale) / 2.0;
+            width = self.width as f32 * scale;
+            height = self.height as f32 * scale;
+            clear_background(BLACK);
+        }
+        draw_texture_ex(
+            self.texture,
+            x,
+            y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(Vec2::new(width, height)),
+                ..Default::default()
+            },
+        );
+        self.frame_times.render = upload_start - render_start;
+        self.frame_times.upload = get_time() - upload_start;
+    }
+
     fn draw(&mut self) {
+        self.frame_times.render = 0.0;
+        self.frame_times.upload = 0.0;
         match self.ui_state {
             UIState::MainMenu => {
-                self.game_state.render(0.3, &mut self.image);
-                self.texture.update(&self.image);
-                draw_texture_ex(
-                    self.texture,
-                    0.0,
-                    0.0,
-                    WHITE,
-                    DrawTextureParams {
-                        dest_size: Some(Vec2::new(screen_width(), screen_height())),
-                        ..Default::default()
-                    },
-                );
+                self.render_frame(0.3);
                 ui::show_title("ray ten");
                 self.current_hovered_item = ui::show_menu(
                     UIState::MainMenu
@@ -319,18 +573,7 @@ impl GameDriver {
                 );
             }
             UIState::Hud => {
-                self.game_state.render(1.0, &mut self.image);
-                self.texture.update(&self.image);
-                draw_texture_ex(
-                    self.texture,
-                    0.0,
-                    0.0,
-                    WHITE,
-                    DrawTextureParams {
-                        dest_size: Some(Vec2::new(screen_width(), screen_height())),
-                        ..Default::default()
-                    },
-                );
+                self.render_frame(1.0);
                 ui::show_hud_top_left(format!("Score: {}", self.score).as_str());
                 if self.since_last_collision < COLLISION_NOTICE_TIMEOUT {
                     if self.last_collision {
@@ -341,18 +584,7 @@ impl GameDriver {
                 }
             }
             UIState::PauseMenu => {
-                self.game_state.render(0.3, &mut self.image);
-                self.texture.update(&self.image);
-                draw_texture_ex(
-                    self.texture,
-                    0.0,
-                    0.0,
-                    WHITE,
-                    DrawTextureParams {
-                        dest_size: Some(Vec2::new(screen_width(), screen_height())),
-                        ..Default::default()
-                    },
-                );
+                self.render_frame(0.3);
                 ui::show_title("Paused");
                 self.current_hovered_item = ui::show_menu(
                     UIState::PauseMenu
@@ -363,6 +595,44 @@ impl GameDriver {
                     mouse_position(),
                 );
             }
+            UIState::Settings => {
+                self.render_frame(0.3);
+                ui::show_title("Settings");
+                let items = UIState::Settings
+                    .menu_items()
+                    .iter()
+                    .map(|&(text, action)| match action {
+                        Action::ToggleCrt => {
+                            format!("{}: {}", text, on_off(self.crt_filter.is_some()))
+                        }
+                        Action::NextPalette => {
+                            format!("{}: {}", text, self.game_state.renderer.palette().name())
+                        }
+                        Action::NextStereo => {
+                            format!("{}: {}", text, self.game_state.renderer.stereo().name())
+                        }
+                        Action::ToggleSpectrumResolution => {
+                            format!("{}: {}", text, on_off(self.spectrum_resolution))
+                        }
+                        Action::ToggleWallMarkers => {
+                            format!("{}: {}", text, on_off(self.game_state.wall_markers()))
+                        }
+                        Action::ToggleInterceptGhost => {
+                            format!("{}: {}", text, on_off(self.game_state.intercept_ghost()))
+                        }
+                        Action::ToggleDepthRing => {
+                            format!("{}: {}", text, on_off(self.game_state.depth_ring()))
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        Action::ToggleRecording => {
+                            format!("{}: {}", text, on_off(self.record_games))
+                        }
+                        _ => text.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                self.current_hovered_item =
+                    ui::show_menu(items, self.current_selected_item, mouse_position());
+            }
             UIState::EndGame => {
                 ui::show_title(format!("Final score: {}", self.score).as_str());
                 self.current_hovered_item = ui::show_menu(
@@ -375,24 +645,189 @@ impl GameDriver {
                 );
             }
         }
+        let mut overlay_lines = vec![];
+        if let Some(free_camera) = &self.free_camera {
+            overlay_lines.extend(self.show_free_camera_overlay(free_camera));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(capture) = &self.capture {
+            overlay_lines.push(format!(
+                "capturing {}, {} frames (F12 stops)",
+                capture.path(),
+                capture.frames()
+            ));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(session) = &self.session {
+            overlay_lines.push(format!("recording {}", session.path()));
+        }
+        let stats = FrameStats {
+            counters: self.game_state.renderer.take_stats(),
+            times: self.frame_times,
+            lanes: self.game_state.renderer.lanes(),
+        };
+        overlay_lines.extend(self.process_stats(&stats));
+        ui::show_debug_lines_top_left(&overlay_lines);
+        self.frames_drawn += 1;
         if mouse_position() != self.prev_mouse_pos {
             self.prev_mouse_pos = mouse_position();
             if let Some(hovered_item) = self.current_hovered_item {
                 self.current_selected_item = hovered_item;
             }
         }
+        let mode = self.game_state.renderer.mode();
         ui::show_debug_bottom_left(
             format!(
-                "fps: {}, rps: {:.4}M",
+                "fps: {}, rps: {:.4}M{}{}{}{}{}{}{}{}",
                 get_fps(),
-                ((self.height as f32) * (self.width as f32) * (SAMPLES_PER_PIXEL as f32)
-                    / get_frame_time()
-                    / 1_000_000f32)
+                (stats.total_rays() as f32 / get_frame_time() / 1_000_000f32),
+                if mode == RenderMode::Shaded {
+                    String::new()
+                } else {
+                    format!(", mode: {}", mode.name())
+                },
+                if self.game_state.adaptive_sampling() {
+                    ", adaptive"
+                } else {
+                    ""
+                },
+                match self.game_state.renderer.interlacing() {
+                    Interlacing::Off => String::new(),
+                    interlacing => format!(", interlacing: {}", interlacing.name()),
+                },
+                if self.game_state.post_processing() {
+                    ", post"
+                } else {
+                    ""
+                },
+                match self.game_state.renderer.tone_mapping() {
+                    ToneMapping::Aces => String::new(),
+                    tone_mapping => format!(", tone mapping: {}", tone_mapping.name()),
+                },
+                if self.game_state.renderer.exposure() == 0.0 {
+                    String::new()
+                } else {
+                    format!(", exposure: {:+}", self.game_state.renderer.exposure())
+                },
+                match self.game_state.renderer.palette() {
+                    Palette::Rgb => String::new(),
+                    palette => format!(", palette: {}", palette.name()),
+                },
+                match self.game_state.renderer.stereo() {
+                    Stereo::Off => String::new(),
+                    stereo => format!(", stereo: {}", stereo.name()),
+                }
             )
             .as_str(),
         );
     }
 
+    fn update_stats_collection(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let collect_stats = self.show_stats || self.stats_csv.is_some();
+        #[cfg(target_arch = "wasm32")]
+        let collect_stats = self.show_stats;
+        self.game_state.renderer.set_collect_stats(collect_stats);
+    }
+
+    fn toggle_crt_filter(&mut self) {
+        self.crt_filter = match self.crt_filter {
+            Some(_) => None,
+            None => Some(CrtFilter::default()),
+        };
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_stats_csv(&mut self) {
+        use std::io::Write;
+
+        if self.stats_csv.take().is_some() {
+            return;
+        }
+        match std::fs::File::create(STATS_CSV_PATH) {
+            Ok(mut file) => {
+                if writeln!(file, "{}", FrameStats::csv_header()).is_ok() {
+                    self.stats_csv = Some(file);
+                }
+            }
+            Err(err) => eprintln!("Can't create {}: {}", STATS_CSV_PATH, err),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn toggle_capture(&mut self) {
+        if self.capture.take().is_some() {
+            return;
+        }
+        match Capture::create(CAPTURE_PATH, CAPTURE_FPS) {
+            Ok(capture) => self.capture = Some(capture),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Records the game starting from a new `GameState` if asked to, stopping the last one.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_session(&mut self) {
+        self.session = None;
+        if !self.record_games {
+            return;
+        }
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        match SessionRecorder::create(&format!("ray-ten-session-{}.txt", seconds)) {
+            Ok(session) => self.session = Some(session),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Dumps the stats of the frame if asked to, returns the overlay lines.
+    fn process_stats(&mut self, stats: &FrameStats) -> Vec<String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(file) = &mut self.stats_csv {
+            use std::io::Write;
+
+            if writeln!(file, "{}", stats.csv_row(self.frames_drawn)).is_err() {
+                self.stats_csv = None;
+                self.update_stats_collection();
+            }
+        }
+        if !self.show_stats {
+            return vec![];
+        }
+        stats.overlay_lines()
+    }
+
+    /// Draws sphere markers, returns the overlay lines.
+    fn show_free_camera_overlay(&self, free_camera: &FreeCamera) -> Vec<String> {
+        let camera = &self.game_state.camera;
+        let format_point =
+            |point: Point| format!("({:.2}, {:.2}, {:.2})", point.x(), point.y(), point.z());
+        let mut lines = vec![
+            format!(
+                "free camera, simulation {} (F1 exits, P pauses)",
+                if free_camera.simulation_paused() {
+                    "paused"
+                } else {
+                    "running"
+                }
+            ),
+            format!("camera: {}", format_point(camera.origin())),
+        ];
+        for (sphere, name) in [
+            (Sphere::Ball, "ball"),
+            (Sphere::NearPaddle, "near paddle"),
+            (Sphere::FarPaddle, "far paddle"),
+        ] {
+            let pos = self.game_state.scene.sphere_pos(sphere);
+            lines.push(format!("{}: {}", name, format_point(pos)));
+            if let Some(screen_pos) = camera.project(pos) {
+                ui::show_debug_marker(screen_pos, name);
+            }
+        }
+        lines
+    }
+
     fn is_pos_in_direction(direction: TouchDirection, pos: Vec2) -> bool {
         match direction {
             TouchDirection::Center => {
@@ -419,3 +854,11 @@ impl GameDriver {
         Self::is_pos_in_direction(direction, mouse_position_local())
     }
 }
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
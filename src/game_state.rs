@@ -0,0 +1,301 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::math::{Color, Directions, Real};
+use crate::motion::{MotionResult, MotionTicker};
+use crate::render::{
+    camera::Camera,
+    post::{PostPass, DEFAULT_POST_PASSES},
+    renderer::Renderer,
+};
+use crate::scene::{Obstacle, Plane, Scene, Sphere};
+
+pub const MAX_DEPTH: usize = 5;
+#[cfg(not(target_arch = "wasm32"))]
+pub const SAMPLES_PER_PIXEL: usize = 2;
+#[cfg(target_arch = "wasm32")]
+pub const SAMPLES_PER_PIXEL: usize = 1;
+// Samples of the pixels adaptive sampling finds on edges.
+pub const EDGE_SAMPLES: usize = 4;
+pub const SHUTTER: f32 = 0.5;
+pub const FIELD_OF_VIEW: f32 = FRAC_PI_2;
+// Colors of the depth ring while the ball heads to the near and to the far paddle.
+pub const DEPTH_RING_COLORS: (Color, Color) =
+    (Color::new(1.0, 0.35, 0.0), Color::new(0.1, 0.15, 1.0));
+
+pub struct GameState {
+    pub camera: Camera,
+    pub scene: Scene,
+    pub motion_ticker: MotionTicker,
+    pub renderer: Renderer,
+    frame: usize,
+    camera_follows_paddle: bool,
+    samples_per_pixel: usize,
+    intercept_ghost: bool,
+    depth_ring: bool,
+    depth_ring_colors: (Color, Color),
+}
+
+#[derive(Clone, Copy)]
+pub enum AdvanceResult {
+    PlayerHit,
+    AIHit,
+    PlayerMiss,
+    AIMiss,
+    None,
+}
+
+impl GameState {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self::with_quality(width, height, SAMPLES_PER_PIXEL, MAX_DEPTH)
+    }
+
+    pub fn with_quality(
+        width: u16,
+        height: u16,
+        samples_per_pixel: usize,
+        max_depth: usize,
+    ) -> Self {
+        let mut renderer = Renderer::new((width, height), samples_per_pixel, max_depth);
+        renderer.set_shutter(SHUTTER);
+        Self {
+            camera: Camera::new(width as f32 / height as f32, FIELD_OF_VIEW),
+            scene: Scene::new(),
+            motion_ticker: MotionTicker::new(),
+            renderer,
+            frame: 0,
+            camera_follows_paddle: true,
+            samples_per_pixel,
+            intercept_ghost: false,
+            depth_ring: false,
+            depth_ring_colors: DEPTH_RING_COLORS,
+        }
+    }
+
+    pub fn advance(
+        &mut self,
+        elapsed: f32,
+        near_paddle_directions: Directions,
+        far_paddle_directions: Directions,
+    ) -> AdvanceResult {
+        self.frame += 1;
+        let motion_result = self.motion_ticker.tick(
+            &mut self.scene,
+            elapsed,
+            near_paddle_directions,
+            far_paddle_directions,
+        );
+        if self.camera_follows_paddle {
+            let near_paddle_pos = self.scene.sphere_pos(Sphere::NearPaddle);
+            self.camera
+                .move_origin_to(near_paddle_pos.x(), near_paddle_pos.y());
+        }
+        self.camera.focus_on(self.scene.sphere_pos(Sphere::Ball));
+        self.update_aids();
+
+        match motion_result {
+            MotionResult::Colision(Obstacle::Plane(Plane::Near)) => AdvanceResult::PlayerMiss,
+            MotionResult::Colision(Obstacle::Plane(Plane::Far)) => AdvanceResult::AIMiss,
+            MotionResult::Colision(Obstacle::Sphere(Sphere::NearPaddle)) => {
+                AdvanceResult::PlayerHit
+            }
+            MotionResult::Colision(Obstacle::Sphere(Sphere::FarPaddle)) => AdvanceResult::AIHit,
+            _ => AdvanceResult::None,
+        }
+    }
+
+    /// Starts the game over from where `new` does, keeping the renderer, the aids and whether
+    /// the camera follows the near paddle.
+    pub fn restart(&mut self) {
+        let wall_markers = self.scene.wall_markers();
+        self.scene = Scene::new();
+        self.scene.set_wall_markers(wall_markers);
+        self.motion_ticker = MotionTicker::new();
+        self.frame = 0;
+        if self.camera_follows_paddle {
+            self.camera = Camera::new(self.camera.aspect_ratio(), FIELD_OF_VIEW);
+        }
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.renderer.set_dimensions((width, height));
+        self.camera.set_aspect_ratio(width as f32 / height as f32);
+    }
+
+    pub fn detach_camera(&mut self) {
+        self.camera_follows_paddle = false;
+    }
+
+    pub fn attach_camera(&mut self, aspect_ratio: f32) {
+        self.camera = Camera::new(aspect_ratio, FIELD_OF_VIEW);
+        self.camera_follows_paddle = true;
+    }
+
+    pub fn adaptive_sampling(&self) -> bool {
+        self.renderer.edge_samples().is_some()
+    }
+
+    /// Renders a single sample per pixel then `EDGE_SAMPLES` more on edges, rather than
+    /// `samples_per_pixel` everywhere.
+    pub fn set_adaptive_sampling(&mut self, adaptive: bool) {
+        if adaptive {
+            self.renderer.set_samples_per_pixel(1);
+            self.renderer.set_edge_samples(Some(EDGE_SAMPLES));
+        } else {
+            self.renderer.set_samples_per_pixel(self.samples_per_pixel);
+            self.renderer.set_edge_samples(None);
+        }
+    }
+
+    pub fn post_processing(&self) -> bool {
+        !self.renderer.post_passes().is_empty()
+    }
+
+    /// Runs every post pass with its default settings, or none.
+    pub fn set_post_processing(&mut self, post_processing: bool) {
+        let post_passes: &[PostPass] = if post_processing {
+            &DEFAULT_POST_PASSES
+        } else {
+            &[]
+        };
+        self.renderer.set_post_passes(post_passes.to_vec());
+    }
+
+    pub fn wall_markers(&self) -> bool {
+        self.scene.wall_markers()
+    }
+
+    pub fn set_wall_markers(&mut self, wall_markers: bool) {
+        self.scene.set_wall_markers(wall_markers);
+    }
+
+    pub fn intercept_ghost(&self) -> bool {
+        self.intercept_ghost
+    }
+
+    /// Shows where the ball is going to reach the near paddle, while it's coming.
+    pub fn set_intercept_ghost(&mut self, intercept_ghost: bool) {
+        self.intercept_ghost = intercept_ghost;
+        self.update_aids();
+    }
+
+    pub fn depth_ring(&self) -> bool {
+        self.depth_ring
+    }
+
+    /// Shows a ring around the room following the ball's depth.
+    pub fn set_depth_ring(&mut self, depth_ring: bool) {
+        self.depth_ring = depth_ring;
+        self.update_aids();
+    }
+
+    /// The ring takes the near color while the ball comes to the near paddle and the far one
+    /// while it goes to the far paddle.
+    pub fn set_depth_ring_colors(&mut self, near: Color, far: Color) {
+        self.depth_ring_colors = (near, far);
+        self.update_aids();
+    }
+
+    /// Hands the aids following the ball to the scene for the tracer to draw.
+    fn update_aids(&mut self) {
+        let intercept = if self.intercept_ghost {
+            self.motion_ticker.predict_intercept(&self.scene)
+        } else {
+            None
+        };
+        self.scene.set_intercept(intercept);
+        let (near_color, far_color) = self.depth_ring_colors;
+        let depth_ring = if !self.depth_ring {
+            None
+        } else if self.motion_ticker.ball_speed().z() > 0.0 {
+            Some(near_color)
+        } else {
+            Some(far_color)
+        };
+        self.scene.set_depth_ring(depth_ring);
+    }
+
+    pub fn render(&mut self, coef: f32, buffer: &mut [[u8; 4]]) {
+        self.renderer
+            .render(&self.scene, &self.camera, self.frame, coef, buffer);
+    }
+}
+
+pub fn control_far_paddle(scene: &Scene) -> Directions {
+    const EPSILON: Real = 0.1;
+    Directions::new(
+        (scene.sphere_pos(Sphere::Ball).y() - scene.sphere_pos(Sphere::FarPaddle).y()) > EPSILON,
+        (scene.sphere_pos(Sphere::FarPaddle).y() - scene.sphere_pos(Sphere::Ball).y()) > EPSILON,
+        (scene.sphere_pos(Sphere::FarPaddle).x() - scene.sphere_pos(Sphere::Ball).x()) > EPSILON,
+        (scene.sphere_pos(Sphere::Ball).x() - scene.sphere_pos(Sphere::FarPaddle).x()) > EPSILON,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_keeps_settings() {
+        let mut game_state = GameState::new(64, 36);
+        let renderer = &mut game_state.renderer;
+        renderer.set_mode(renderer.mode().next());
+        renderer.set_interlacing(renderer.interlacing().next());
+        renderer.set_stereo(renderer.stereo().next());
+        renderer.set_tone_mapping(renderer.tone_mapping().next());
+        renderer.set_palette(renderer.palette().next());
+        game_state.set_adaptive_sampling(true);
+        game_state.set_post_processing(true);
+        game_state.renderer.set_exposure(1.5);
+        game_state.set_wall_markers(true);
+        game_state.set_intercept_ghost(true);
+        game_state.set_depth_ring(true);
+        let ring_colors = (Color::new(0.1, 0.2, 0.3), Color::new(0.4, 0.5, 0.6));
+        game_state.set_depth_ring_colors(ring_colors.0, ring_colors.1);
+
+        let settings = |game_state: &GameState| {
+            let renderer = &game_state.renderer;
+            (
+                (
+                    renderer.mode(),
+                    renderer.interlacing(),
+                    renderer.stereo(),
+                    renderer.tone_mapping(),
+                    renderer.palette(),
+                    renderer.exposure(),
+                ),
+                (
+                    game_state.adaptive_sampling(),
+                    game_state.post_processing(),
+                    game_state.wall_markers(),
+                    game_state.intercept_ghost(),
+                    game_state.depth_ring(),
+                ),
+            )
+        };
+        let before = settings(&game_state);
+        let directions = Directions::new(false, false, false, false);
+        for _ in 0..10 {
+            game_state.advance(0.1, directions, directions);
+        }
+        game_state.restart();
+        assert_eq!(settings(&game_state), before);
+
+        assert_eq!(
+            game_state.scene.sphere_pos(Sphere::Ball),
+            Scene::new().sphere_pos(Sphere::Ball)
+        );
+        game_state.advance(0.1, directions, directions);
+        let ring = game_state.scene.depth_ring();
+        assert!(ring == Some(ring_colors.0) || ring == Some(ring_colors.1));
+    }
+
+    #[test]
+    fn restart_keeps_detached_camera() {
+        let mut game_state = GameState::new(64, 36);
+        game_state.detach_camera();
+        game_state.camera.move_origin_to(0.3, -0.2);
+        game_state.restart();
+        let origin = game_state.camera.origin();
+        assert_eq!((origin.x(), origin.y()), (0.3, -0.2));
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::capture::Capture;
+use crate::game_state::{control_far_paddle, GameState, MAX_DEPTH, SAMPLES_PER_PIXEL, SHUTTER};
+use crate::math::{Color, Real, SimdLevel, SUPPORTED_LANES};
+use crate::render::crt::CrtFilter;
+use crate::render::post::{PostPass, ToneMapping};
+use crate::render::renderer::{Interlacing, Stereo};
+use crate::render::spectrum::Palette;
+use crate::render::stats::{FrameStats, FrameTimes, TraceCounters};
+use crate::render::tracer::RenderMode;
+use crate::session;
+use crate::terminal::Output;
+
+const USAGE: &str = "usage:
+  ray-ten render --output FILE.png [--time SECONDS] [RENDER OPTIONS]
+  ray-ten capture --output DIRECTORY|FILE.y4m [--fps N] [--seconds SECONDS] [RENDER OPTIONS]
+  ray-ten replay --session FILE.txt --output DIRECTORY|FILE.y4m [--fps N] [RENDER OPTIONS]
+  ray-ten terminal [--sixel true|false] [--width PIXELS] [--height PIXELS]
+  ray-ten bench [--seconds SECONDS] [--threads MAX] [--samples N,...] [--depths N,...]
+    [--lanes 4|8|16,...] [--width PIXELS] [--height PIXELS]
+render options:
+    [--mode shaded|normals|depth|ids|bounces] [--width PIXELS] [--height PIXELS] [--samples N]
+    [--edge-samples N] [--depth N] [--shutter FRACTION] [--interlacing off|rows|checkerboard]
+    [--exposure STOPS]
+    [--tone-mapping clamp|reinhard|aces|filmic] [--palette rgb|spectrum|spectrum-attributes]
+    [--post bloom,chromatic-aberration,vignette,film-grain] [--crt true|false]
+    [--stereo off|anaglyph|side-by-side] [--wall-markers true|false]
+    [--intercept-ghost true|false] [--depth-ring true|false] [--ring-colors RRGGBB,RRGGBB]
+replays default to 1920x1080 pixels, 16 samples, a depth of 8 and a shutter of 1";
+
+const DEFAULT_WIDTH: u16 = 1600;
+const DEFAULT_HEIGHT: u16 = 900;
+// Headless runs advance the simulation in fixed 60 fps steps.
+const TICK: f32 = 1.0 / 60.0;
+const DEFAULT_CAPTURE_FPS: u32 = 30;
+
+/// Defaults of the options setting how much work rendering a frame takes.
+struct Quality {
+    dimensions: (u16, u16),
+    samples_per_pixel: usize,
+    max_depth: usize,
+    shutter: f32,
+}
+
+const GAME_QUALITY: Quality = Quality {
+    dimensions: (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+    samples_per_pixel: SAMPLES_PER_PIXEL,
+    max_depth: MAX_DEPTH,
+    shutter: SHUTTER,
+};
+// Replays are rendered to be watched later, there's no frame rate to keep up.
+const REPLAY_QUALITY: Quality = Quality {
+    dimensions: (1920, 1080),
+    samples_per_pixel: 16,
+    max_depth: 8,
+    shutter: 1.0,
+};
+
+/// Runs a command line mode without opening a window.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (command, args) = args.split_first().ok_or(USAGE)?;
+    let options = Options::parse(args)?;
+    match command.as_str() {
+        "render" => render(&options),
+        "bench" => bench(&options),
+        "capture" => capture(&options),
+        "replay" => replay(&options),
+        "terminal" => terminal(&options),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+struct Options {
+    values: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument '{}'\n{}", arg, USAGE))?;
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for '{}'\n{}", arg, USAGE))?;
+            values.insert(name.to_string(), value.clone());
+        }
+        Ok(Self { values })
+    }
+
+    fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.values.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value '{}' for '--{}'", value, name)),
+            None => Ok(default),
+        }
+    }
+
+    /// Comma separated values.
+    fn get_list<T: FromStr>(&self, name: &str, default: Vec<T>) -> Result<Vec<T>, String> {
+        match self.values.get(name) {
+            Some(values) => values
+                .split(',')
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("invalid value '{}' for '--{}'", value, name))
+                })
+                .collect(),
+            None => Ok(default),
+        }
+    }
+
+    /// Like `get`, rejecting zeros for sizes and counts nothing can be rendered with.
+    fn get_nonzero<T: FromStr + Default + PartialEq>(
+        &self,
+        name: &str,
+        default: T,
+    ) -> Result<T, String> {
+        let value = self.get(name, default)?;
+        nonzero(name, &value)?;
+        Ok(value)
+    }
+
+    /// Like `get_list`, rejecting zeros for sizes and counts nothing can be rendered with.
+    fn get_nonzero_list<T: FromStr + Default + PartialEq>(
+        &self,
+        name: &str,
+        default: Vec<T>,
+    ) -> Result<Vec<T>, String> {
+        let values = self.get_list(name, default)?;
+        for value in &values {
+            nonzero(name, value)?;
+        }
+        Ok(values)
+    }
+
+    fn get_str(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+fn nonzero<T: Default + PartialEq>(name: &str, value: &T) -> Result<(), String> {
+    if *value == T::default() {
+        return Err(format!("'--{}' must be above 0\n{}", name, USAGE));
+    }
+    Ok(())
+}
+
+/// Plays the attract mode for `time` seconds, both paddles following the ball.
+fn simulate(game_state: &mut GameState, time: f32) {
+    for _ in 0..(time / TICK).round() as usize {
+        let directions = control_far_paddle(&game_state.scene);
+        game_state.advance(TICK, directions, directions);
+    }
+}
+
+/// Color written as `RRGGBB` hex digits.
+fn parse_color(hex: &str) -> Option<Color> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16)
+            .ok()
+            .map(|channel| channel as Real / 255.0)
+    };
+    Some(Color::new(channel(0)?, channel(1)?, channel(2)?))
+}
+
+fn save_png(path: &str, width: u16, height: u16, buffer: &[[u8; 4]]) -> Result<(), String> {
+    image::save_buffer(
+        path,
+        &buffer.concat(),
+        width as u32,
+        height as u32,
+        image::ColorType::Rgba8,
+    )
+    .map_err(|err| format!("can't write '{}': {}", path, err))
+}
+
+fn dimensions(options: &Options, quality: &Quality) -> Result<(u16, u16), String> {
+    Ok((
+        options.get_nonzero("width", quality.dimensions.0)?,
+        options.get_nonzero("height", quality.dimensions.1)?,
+    ))
+}
+
+/// Game state at the start of a game, rendering with the options `render`, `capture` and
+/// `replay` share.
+fn configured_game_state(options: &Options, quality: &Quality) -> Result<GameState, String> {
+    let mode_name = options.get_str("mode").unwrap_or("shaded");
+    let mode =
+        RenderMode::from_name(mode_name).ok_or_else(|| format!("unknown mode '{}'", mode_name))?;
+    let interlacing_name = options.get_str("interlacing").unwrap_or("off");
+    let interlacing = Interlacing::from_name(interlacing_name)
+        .ok_or_else(|| format!("unknown interlacing '{}'", interlacing_name))?;
+    let tone_mapping_name = options.get_str("tone-mapping").unwrap_or("aces");
+    let tone_mapping = ToneMapping::from_name(tone_mapping_name)
+        .ok_or_else(|| format!("unknown tone mapping '{}'", tone_mapping_name))?;
+    let stereo_name = options.get_str("stereo").unwrap_or("off");
+    let stereo = Stereo::from_name(stereo_name)
+        .ok_or_else(|| format!("unknown stereo '{}'", stereo_name))?;
+    let palette_name = options.get_str("palette").unwrap_or("rgb");
+    let palette = Palette::from_name(palette_name)
+        .ok_or_else(|| format!("unknown palette '{}'", palette_name))?;
+    let (width, height) = dimensions(options, quality)?;
+
+    let mut game_state = GameState::with_quality(
+        width,
+        height,
+        options.get_nonzero("samples", quality.samples_per_pixel)?,
+        options.get_nonzero("depth", quality.max_depth)?,
+    );
+    game_state
+        .renderer
+        .set_shutter(options.get("shutter", quality.shutter)?);
+    game_state.renderer.set_mode(mode);
+    let edge_samples = options.get("edge-samples", 0)?;
+    if edge_samples > 0 {
+        game_state.renderer.set_edge_samples(Some(edge_samples));
+    }
+    game_state.renderer.set_interlacing(interlacing);
+    game_state
+        .renderer
+        .set_exposure(options.get("exposure", 0.0)?);
+    game_state.renderer.set_tone_mapping(tone_mapping);
+    game_state.renderer.set_palette(palette);
+    game_state.renderer.set_stereo(stereo);
+    game_state.set_wall_markers(options.get("wall-markers", false)?);
+    game_state.set_intercept_ghost(options.get("intercept-ghost", false)?);
+    game_state.set_depth_ring(options.get("depth-ring", false)?);
+    if let Some(colors) = options.get_str("ring-colors") {
+        let invalid = || format!("invalid value '{}' for '--ring-colors'", colors);
+        let (near, far) = colors.split_once(',').ok_or_else(invalid)?;
+        game_state.set_depth_ring_colors(
+            parse_color(near).ok_or_else(invalid)?,
+            parse_color(far).ok_or_else(invalid)?,
+        );
+    }
+    let mut post_passes = Vec::new();
+    for name in options.get_list::<String>("post", Vec::new())? {
+        post_passes.push(
+            PostPass::from_name(&name).ok_or_else(|| format!("unknown post pass '{}'", name))?,
+        );
+    }
+    game_state.renderer.set_post_passes(post_passes);
+    Ok(game_state)
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let output = options.get_str("output").ok_or(USAGE)?;
+    let mut game_state = configured_game_state(options, &GAME_QUALITY)?;
+    let (width, height) = dimensions(options, &GAME_QUALITY)?;
+    let mut time = options.get("time", 0.0)?;
+
+    let mut buffer = vec![[0, 0, 0, 255]; width as usize * height as usize];
+    // Interlaced frames reproject half of their pixels from the frame before.
+    if game_state.renderer.interlacing() != Interlacing::Off && time >= TICK {
+        simulate(&mut game_state, time - TICK);
+        game_state.render(1.0, &mut buffer);
+        time = TICK;
+    }
+    simulate(&mut game_state, time);
+    game_state.render(1.0, &mut buffer);
+    if options.get("crt", false)? {
+        CrtFilter::default().apply(&mut buffer, width as usize);
+    }
+    save_png(output, width, height, &buffer)
+}
+
+/// Renders `seconds` of the attract mode as video frames, advancing the same time for every one.
+fn capture(options: &Options) -> Result<(), String> {
+    let output = options.get_str("output").ok_or(USAGE)?;
+    let mut game_state = configured_game_state(options, &GAME_QUALITY)?;
+    let (width, height) = dimensions(options, &GAME_QUALITY)?;
+    let mut capture = Capture::create(output, options.get("fps", DEFAULT_CAPTURE_FPS)?)?;
+    let frames = (options.get("seconds", 10.0)? / capture.frame_time()).round() as usize;
+    let mut crt_filter = options.get("crt", false)?.then(CrtFilter::default);
+
+    let mut buffer = vec![[0, 0, 0, 255]; width as usize * height as usize];
+    for _ in 0..frames {
+        let directions = control_far_paddle(&game_state.scene);
+        game_state.advance(capture.frame_time(), directions, directions);
+        game_state.render(1.0, &mut buffer);
+        if let Some(crt_filter) = &mut crt_filter {
+            crt_filter.apply(&mut buffer, width as usize);
+        }
+        capture.write_frame(&buffer, width, height)?;
+    }
+    Ok(())
+}
+
+/// Re-simulates a recorded session step by step, rendering video frames the same time apart.
+fn replay(options: &Options) -> Result<(), String> {
+    let session = options.get_str("session").ok_or(USAGE)?;
+    let output = options.get_str("output").ok_or(USAGE)?;
+    let mut steps = session::load(session)?.into_iter();
+    let mut game_state = configured_game_state(options, &REPLAY_QUALITY)?;
+    let (width, height) = dimensions(options, &REPLAY_QUALITY)?;
+    let mut capture = Capture::create(output, options.get("fps", DEFAULT_CAPTURE_FPS)?)?;
+    let frame_time = capture.frame_time() as f64;
+    let mut crt_filter = options.get("crt", false)?.then(CrtFilter::default);
+
+    let mut buffer = vec![[0, 0, 0, 255]; width as usize * height as usize];
+    // Seconds simulated so far and when the frame being rendered ends, the steps the game took
+    // rarely lining up with frames.
+    let (mut time, mut frame_end) = (0.0, frame_time);
+    loop {
+        let frame_start = game_state.scene.sphere_positions();
+        while time < frame_end {
+            let step = match steps.next() {
+                Some(step) => step,
+                None => return Ok(()),
+            };
+            game_state.advance(step.elapsed, step.near_directions, step.far_directions);
+            time += step.elapsed as f64;
+        }
+        // Blurs the motion over the whole frame rather than its last step.
+        game_state.scene.set_previous_positions(frame_start);
+        game_state.render(1.0, &mut buffer);
+        if let Some(crt_filter) = &mut crt_filter {
+            crt_filter.apply(&mut buffer, width as usize);
+        }
+        capture.write_frame(&buffer, width, height)?;
+        frame_end += frame_time;
+    }
+}
+
+/// Plays in the terminal, sixel frames being sized to it unless both dimensions are given.
+fn terminal(options: &Options) -> Result<(), String> {
+    let output = if options.get("sixel", false)? {
+        match (options.get_str("width"), options.get_str("height")) {
+            (Some(_), Some(_)) => Output::Sixel(Some((
+                options.get_nonzero("width", 0)?,
+                options.get_nonzero("height", 0)?,
+            ))),
+            _ => Output::Sixel(None),
+        }
+    } else {
+        Output::HalfBlocks
+    };
+    crate::terminal::run(output)
+}
+
+/// Renders the attract mode from the start over and over for every combination of lane count,
+/// thread count, samples per pixel and depth, printing a CSV line per combination.
+fn bench(options: &Options) -> Result<(), String> {
+    let seconds: f64 = options.get("seconds", 5.0)?;
+    if !(seconds.is_finite() && seconds > 0.0) {
+        return Err(format!("'--seconds' must be above 0\n{}", USAGE));
+    }
+    let max_threads = options.get("threads", rayon::current_num_threads())?;
+    let samples = options.get_nonzero_list("samples", vec![1, 2, 4])?;
+    let depths = options.get_nonzero_list("depths", vec![1, 3, MAX_DEPTH])?;
+    // By default every lane count up to the widest the CPU has registers for.
+    let simd_level = SimdLevel::detect();
+    let widest = simd_level.lanes();
+    let lanes = options.get_list(
+        "lanes",
+        SUPPORTED_LANES
+            .into_iter()
+            .filter(|&lanes| lanes <= widest)
+            .collect(),
+    )?;
+    if let Some(unsupported) = lanes.iter().find(|lanes| !SUPPORTED_LANES.contains(lanes)) {
+        return Err(format!("unsupported lane count {}", unsupported));
+    }
+    let width = options.get_nonzero("width", DEFAULT_WIDTH)?;
+    let height = options.get_nonzero("height", DEFAULT_HEIGHT)?;
+
+    println!(
+        "lanes,simd,threads,samples,depth,width,height,frames,seconds,fps,rays,rays_per_second"
+    );
+    for &lanes in &lanes {
+        for threads in 1..=max_threads {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|err| format!("can't start {} threads: {}", threads, err))?;
+            for &samples_per_pixel in &samples {
+                for &max_depth in &depths {
+                    let (frames, stats) = pool.install(|| {
+                        bench_configuration(
+                            width,
+                            height,
+                            lanes,
+                            samples_per_pixel,
+                            max_depth,
+                            seconds,
+                        )
+                    });
+                    println!(
+                        "{},{},{},{},{},{},{},{},{:.3},{:.2},{},{:.0}",
+                        lanes,
+                        simd_level.for_lanes(lanes).name(),
+                        threads,
+                        samples_per_pixel,
+                        max_depth,
+                        width,
+                        height,
+                        frames,
+                        stats.times.render,
+                        frames as f64 / stats.times.render,
+                        stats.total_rays(),
+                        stats.rays_per_second()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn bench_configuration(
+    width: u16,
+    height: u16,
+    lanes: usize,
+    samples_per_pixel: usize,
+    max_depth: usize,
+    seconds: f64,
+) -> (usize, FrameStats) {
+    let mut game_state = GameState::with_quality(width, height, samples_per_pixel, max_depth);
+    game_state.renderer.set_lanes(lanes);
+    // Traced rays are counted either way, the rest of the stats would slow the timed frames.
+    game_state.renderer.set_collect_stats(false);
+    let mut buffer = vec![[0, 0, 0, 255]; width as usize * height as usize];
+    let mut counters = TraceCounters::default();
+    let mut rendering = Duration::ZERO;
+    let mut frames = 0;
+
+    while rendering.as_secs_f64() < seconds {
+        simulate(&mut game_state, TICK);
+        let start = Instant::now();
+        game_state.render(1.0, &mut buffer);
+        rendering += start.elapsed();
+        frames += 1;
+
+        counters.merge(&game_state.renderer.take_stats());
+    }
+
+    (
+        frames,
+        FrameStats {
+            counters,
+            times: FrameTimes {
+                render: rendering.as_secs_f64(),
+                ..Default::default()
+            },
+            lanes,
+        },
+    )
+}
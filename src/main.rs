@@ -1,9 +1,12 @@
-#![feature(portable_simd)]
+#![cfg_attr(not(feature = "stable"), feature(portable_simd))]
 
 use macroquad::prelude::*;
 
 #[macro_use]
 extern crate lazy_static;
+// Ahead of the other modules, for them to see its macro.
+#[macro_use]
+mod named_enum;
 
 #[cfg(not(target_arch = "wasm32"))]
 const IMAGE_WIDTH: u16 = 1600;
@@ -14,11 +17,24 @@ const IMAGE_HEIGHT: u16 = 900;
 #[cfg(target_arch = "wasm32")]
 const IMAGE_HEIGHT: u16 = 360;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod capture;
+mod free_camera;
 mod game_driver;
+mod game_state;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
 mod math;
 mod motion;
 mod render;
 mod scene;
+#[cfg(not(target_arch = "wasm32"))]
+mod session;
+#[cfg(any(feature = "stable", test))]
+#[cfg_attr(not(feature = "stable"), allow(dead_code))]
+mod stable_simd;
+#[cfg(not(target_arch = "wasm32"))]
+mod terminal;
 mod ui;
 
 fn window_conf() -> Conf {
@@ -32,12 +48,29 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
+fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        if !args.is_empty() {
+            if let Err(err) = headless::run(&args) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+    macroquad::Window::from_config(window_conf(), run());
+}
+
+async fn run() {
     let mut game_driver = game_driver::GameDriver::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+    // Closing the window ends the loop instead of the process, for the driver to be dropped
+    // and finish writing its files.
+    prevent_quit();
 
     loop {
-        if !game_driver.next_frame() {
+        if is_quit_requested() || !game_driver.next_frame() {
             break;
         }
         next_frame().await
@@ -1,36 +1,145 @@
+#[cfg(feature = "stable")]
+pub use crate::stable_simd::{
+    LaneCount, SimdFloat, SimdInt, SimdPartialEq, SimdPartialOrd, StdFloat, SupportedLaneCount,
+};
+#[cfg(feature = "stable")]
+use crate::stable_simd::{Mask as SimdMask, Simd};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
-use std::simd::{Mask as SimdMask, Simd, SimdFloat, StdFloat};
+#[cfg(not(feature = "stable"))]
+pub use std::simd::{
+    LaneCount, SimdFloat, SimdInt, SimdPartialEq, SimdPartialOrd, StdFloat, SupportedLaneCount,
+};
+#[cfg(not(feature = "stable"))]
+use std::simd::{Mask as SimdMask, Simd};
 
-#[cfg(target_feature = "avx512")]
-pub const LANES: usize = 16usize;
+pub type Real = f32;
+pub type Integer = i32;
+pub type Reals<const N: usize> = Simd<Real, N>;
+pub type Integers<const N: usize> = Simd<Integer, N>;
+pub type Mask<const N: usize> = SimdMask<i32, N>;
+
+/// Widest SIMD instruction set the tracer is compiled for that the running CPU supports.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    not(any(target_arch = "x86", target_arch = "x86_64")),
+    allow(dead_code)
+)]
+pub enum SimdLevel {
+    Base,
+    Avx2,
+    Avx512,
+}
 
-#[cfg(all(target_feature = "avx", not(target_feature = "avx512")))]
-pub const LANES: usize = 8usize;
+impl SimdLevel {
+    pub fn detect() -> SimdLevel {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx512f") {
+                return SimdLevel::Avx512;
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                return SimdLevel::Avx2;
+            }
+        }
+        SimdLevel::Base
+    }
 
-#[cfg(target_feature = "simd128")]
-pub const LANES: usize = 4usize;
+    /// Lanes filling a whole register.
+    pub fn lanes(&self) -> usize {
+        match self {
+            SimdLevel::Base => 4,
+            SimdLevel::Avx2 => 8,
+            SimdLevel::Avx512 => 16,
+        }
+    }
 
-#[cfg(not(any(
-    target_feature = "simd128",
-    target_feature = "avx",
-    target_feature = "avx512"
-)))]
-pub const LANES: usize = 4usize;
+    /// Instruction set `lanes` lanes are traced with on this CPU, its entry points only take
+    /// lanes filling their registers and the rest go through the base one.
+    pub fn for_lanes(&self, lanes: usize) -> SimdLevel {
+        match (lanes, self) {
+            (16, SimdLevel::Avx512) => SimdLevel::Avx512,
+            (8, SimdLevel::Avx2 | SimdLevel::Avx512) => SimdLevel::Avx2,
+            _ => SimdLevel::Base,
+        }
+    }
 
-pub type Real = f32;
-pub type Integer = i32;
-pub type Reals = Simd<Real, LANES>;
-pub type Integers = Simd<Integer, LANES>;
-pub type Mask = SimdMask<i32, LANES>;
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimdLevel::Base => "base",
+            SimdLevel::Avx2 => "avx2",
+            SimdLevel::Avx512 => "avx512",
+        }
+    }
+}
+
+/// Lane counts the tracer is compiled for.
+pub const SUPPORTED_LANES: [usize; 3] = [4, 8, 16];
 
-pub const ZEROS: Reals = Reals::from_array([0.0; LANES]);
-pub const ZERO_POINTS: Points = Points::splat(0.0, 0.0, 0.0);
+pub const fn zeros<const N: usize>() -> Reals<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    splat_reals(0.0)
+}
 
-pub const fn splat_reals(x: Real) -> Reals {
-    Reals::from_array([x; LANES])
+pub const fn splat_reals<const N: usize>(x: Real) -> Reals<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    Reals::from_array([x; N])
 }
 
-pub fn update_reals_if(values: &mut Reals, mask: Mask, update_with: Reals) {
+/// Sines and cosines of `angles` within `[0, 2 * PI)`, good to about 1e-5.
+pub fn sin_cos<const N: usize>(angles: Reals<N>) -> (Reals<N>, Reals<N>)
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let pis = Reals::splat(PI);
+    let half_pis = Reals::splat(FRAC_PI_2);
+    // Fold into [-PI / 2, PI / 2], where both Taylor series converge fast.
+    let mut xs = angles - pis;
+    let upper_mask = xs.simd_gt(half_pis);
+    let lower_mask = xs.simd_lt(-half_pis);
+    xs = upper_mask.select(pis - xs, xs);
+    xs = lower_mask.select(-pis - xs, xs);
+    let squares = xs * xs;
+    let mut sines = Reals::splat(1.0 / 362_880.0);
+    sines = sines * squares - Reals::splat(1.0 / 5_040.0);
+    sines = sines * squares + Reals::splat(1.0 / 120.0);
+    sines = sines * squares - Reals::splat(1.0 / 6.0);
+    sines = sines * squares + Reals::splat(1.0);
+    sines *= xs;
+    let mut cosines = Reals::splat(-1.0 / 3_628_800.0);
+    cosines = cosines * squares + Reals::splat(1.0 / 40_320.0);
+    cosines = cosines * squares - Reals::splat(1.0 / 720.0);
+    cosines = cosines * squares + Reals::splat(1.0 / 24.0);
+    cosines = cosines * squares - Reals::splat(1.0 / 2.0);
+    cosines = cosines * squares + Reals::splat(1.0);
+    cosines = (upper_mask | lower_mask).select(-cosines, cosines);
+    // Undo the initial shift by PI.
+    (-sines, -cosines)
+}
+
+/// Maps points of the `[0, 1)` square to the unit disk in the XY plane, preserving
+/// uniformity.
+pub fn square_to_disk<const N: usize>(xs: Reals<N>, ys: Reals<N>) -> Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let radii = xs.sqrt();
+    let (sines, cosines) = sin_cos(ys * Reals::splat(TAU));
+    Points {
+        xs: cosines * radii,
+        ys: sines * radii,
+        zs: zeros(),
+    }
+}
+
+pub fn update_reals_if<const N: usize>(values: &mut Reals<N>, mask: Mask<N>, update_with: Reals<N>)
+where
+    LaneCount<N>: SupportedLaneCount,
+{
     *values = mask.select(update_with, *values);
 }
 
@@ -42,16 +151,24 @@ pub enum Axis {
 }
 
 #[derive(Copy, Clone, Debug)]
-pub struct Points {
-    pub xs: Reals,
-    pub ys: Reals,
-    pub zs: Reals,
+pub struct Points<const N: usize>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    pub xs: Reals<N>,
+    pub ys: Reals<N>,
+    pub zs: Reals<N>,
 }
 
-pub type Colors = Points;
-pub type Vectors = Points;
+pub type Colors<const N: usize> = Points<N>;
+pub type Vectors<const N: usize> = Points<N>;
+
+impl<const N: usize> Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    pub const ZERO: Points<N> = Points::splat(0.0, 0.0, 0.0);
 
-impl Points {
     pub const fn splat(x: f32, y: f32, z: f32) -> Self {
         Points {
             xs: splat_reals(x),
@@ -60,11 +177,15 @@ impl Points {
         }
     }
 
+    pub fn lane(&self, lane: usize) -> Point {
+        Point::new(self.xs[lane], self.ys[lane], self.zs[lane])
+    }
+
     pub fn from_single(point: Point) -> Self {
         Self::splat(point.x(), point.y(), point.z())
     }
 
-    pub fn get_axis(&self, axis: Axis) -> &Reals {
+    pub fn get_axis(&self, axis: Axis) -> &Reals<N> {
         match axis {
             Axis::XS => &self.xs,
             Axis::YS => &self.ys,
@@ -72,39 +193,28 @@ impl Points {
         }
     }
 
-    pub fn sqrt(self) -> Self {
-        Points {
-            xs: self.xs.sqrt(),
-            ys: self.ys.sqrt(),
-            zs: self.zs.sqrt(),
-        }
-    }
-
-    pub fn normalize(self) -> Self {
-        let zeros = Reals::splat(0.0);
-        let ones = Reals::splat(1.0);
-        Points {
-            xs: self.xs.simd_clamp(zeros, ones),
-            ys: self.ys.simd_clamp(zeros, ones),
-            zs: self.zs.simd_clamp(zeros, ones),
-        }
+    pub fn dot(&self, rhs: Points<N>) -> Reals<N> {
+        self.xs * rhs.xs + self.ys * rhs.ys + self.zs * rhs.zs
     }
 
-    pub fn dot(&self, rhs: Points) -> Reals {
-        self.xs * rhs.xs + self.ys * rhs.ys + self.zs * rhs.zs
+    pub fn length(&self) -> Reals<N> {
+        self.dot(*self).sqrt()
     }
 
-    pub fn update_if(&mut self, mask: Mask, update_with: Points) {
+    pub fn update_if(&mut self, mask: Mask<N>, update_with: Points<N>) {
         self.xs = mask.select(update_with.xs, self.xs);
         self.ys = mask.select(update_with.ys, self.ys);
         self.zs = mask.select(update_with.zs, self.zs);
     }
 }
 
-impl Mul<Reals> for Points {
-    type Output = Points;
+impl<const N: usize> Mul<Reals<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
 
-    fn mul(self, rhs: Reals) -> Self::Output {
+    fn mul(self, rhs: Reals<N>) -> Self::Output {
         Points {
             xs: self.xs * rhs,
             ys: self.ys * rhs,
@@ -113,18 +223,24 @@ impl Mul<Reals> for Points {
     }
 }
 
-impl MulAssign<Reals> for Points {
-    fn mul_assign(&mut self, rhs: Reals) {
+impl<const N: usize> MulAssign<Reals<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    fn mul_assign(&mut self, rhs: Reals<N>) {
         self.xs *= rhs;
         self.ys *= rhs;
         self.zs *= rhs;
     }
 }
 
-impl Mul<Points> for Points {
-    type Output = Points;
+impl<const N: usize> Mul<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
 
-    fn mul(self, rhs: Points) -> Self::Output {
+    fn mul(self, rhs: Points<N>) -> Self::Output {
         Points {
             xs: self.xs * rhs.xs,
             ys: self.ys * rhs.ys,
@@ -133,18 +249,24 @@ impl Mul<Points> for Points {
     }
 }
 
-impl MulAssign<Points> for Points {
-    fn mul_assign(&mut self, rhs: Points) {
+impl<const N: usize> MulAssign<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    fn mul_assign(&mut self, rhs: Points<N>) {
         self.xs *= rhs.xs;
         self.ys *= rhs.ys;
         self.zs *= rhs.zs;
     }
 }
 
-impl Div<Reals> for Points {
-    type Output = Points;
+impl<const N: usize> Div<Reals<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
 
-    fn div(self, rhs: Reals) -> Self::Output {
+    fn div(self, rhs: Reals<N>) -> Self::Output {
         Points {
             xs: self.xs / rhs,
             ys: self.ys / rhs,
@@ -153,16 +275,22 @@ impl Div<Reals> for Points {
     }
 }
 
-impl DivAssign<Reals> for Points {
-    fn div_assign(&mut self, rhs: Reals) {
+impl<const N: usize> DivAssign<Reals<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    fn div_assign(&mut self, rhs: Reals<N>) {
         self.xs /= rhs;
         self.ys /= rhs;
         self.zs /= rhs;
     }
 }
 
-impl Mul<Real> for Points {
-    type Output = Points;
+impl<const N: usize> Mul<Real> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
 
     fn mul(self, rhs: Real) -> Self::Output {
         Points {
@@ -173,7 +301,10 @@ impl Mul<Real> for Points {
     }
 }
 
-impl MulAssign<Real> for Points {
+impl<const N: usize> MulAssign<Real> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
     fn mul_assign(&mut self, rhs: Real) {
         self.xs *= Reals::splat(rhs);
         self.ys *= Reals::splat(rhs);
@@ -181,9 +312,12 @@ impl MulAssign<Real> for Points {
     }
 }
 
-impl Add<Points> for Points {
-    type Output = Points;
-    fn add(self, rhs: Points) -> Self::Output {
+impl<const N: usize> Add<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
+    fn add(self, rhs: Points<N>) -> Self::Output {
         Points {
             xs: self.xs + rhs.xs,
             ys: self.ys + rhs.ys,
@@ -192,17 +326,23 @@ impl Add<Points> for Points {
     }
 }
 
-impl AddAssign<Points> for Points {
-    fn add_assign(&mut self, rhs: Points) {
+impl<const N: usize> AddAssign<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    fn add_assign(&mut self, rhs: Points<N>) {
         self.xs += rhs.xs;
         self.ys += rhs.ys;
         self.zs += rhs.zs;
     }
 }
 
-impl Sub<Points> for Points {
-    type Output = Points;
-    fn sub(self, rhs: Points) -> Self::Output {
+impl<const N: usize> Sub<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    type Output = Points<N>;
+    fn sub(self, rhs: Points<N>) -> Self::Output {
         Points {
             xs: self.xs - rhs.xs,
             ys: self.ys - rhs.ys,
@@ -211,8 +351,11 @@ impl Sub<Points> for Points {
     }
 }
 
-impl SubAssign<Points> for Points {
-    fn sub_assign(&mut self, rhs: Points) {
+impl<const N: usize> SubAssign<Points<N>> for Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    fn sub_assign(&mut self, rhs: Points<N>) {
         self.xs -= rhs.xs;
         self.ys -= rhs.ys;
         self.zs -= rhs.zs;
@@ -220,17 +363,122 @@ impl SubAssign<Points> for Points {
 }
 
 #[derive(Clone, Debug)]
-pub struct Rays {
-    pub origins: Points,
-    pub dirs: Vectors,
+pub struct Rays<const N: usize>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    pub origins: Points<N>,
+    pub dirs: Vectors<N>,
 }
 
-impl Rays {
-    pub fn new(origins: Points, dirs: Points) -> Self {
+impl<const N: usize> Rays<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    pub fn new(origins: Points<N>, dirs: Points<N>) -> Self {
         Self { origins, dirs }
     }
 }
 
+type Seeds<const N: usize> = Simd<u32, N>;
+
+fn hash_seeds<const N: usize>(mut seeds: Seeds<N>) -> Seeds<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    seeds ^= seeds >> Seeds::splat(16);
+    seeds *= Seeds::splat(0x7feb352d);
+    seeds ^= seeds >> Seeds::splat(15);
+    seeds *= Seeds::splat(0x846ca68b);
+    seeds ^= seeds >> Seeds::splat(16);
+    seeds
+}
+
+/// Lane-parallel xorshift generator, every lane runs its own stream.
+///
+/// Streams are derived from the pixel, the sample and the frame, so rendering
+/// the same frame twice gives the same image.
+#[derive(Clone, Debug)]
+pub struct Random<const N: usize>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    state: Seeds<N>,
+}
+
+impl<const N: usize> Random<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    pub fn new(pixel_ids: Integers<N>, sample: usize, frame: usize) -> Self {
+        let stream =
+            hash_seeds(Seeds::splat(frame as u32) ^ hash_seeds(Seeds::splat(sample as u32)));
+        let state = hash_seeds(pixel_ids.cast::<u32>() ^ stream);
+        Self {
+            state: state | Seeds::splat(1),
+        }
+    }
+
+    fn next_seeds(&mut self) -> Seeds<N> {
+        self.state ^= self.state << Seeds::splat(13);
+        self.state ^= self.state >> Seeds::splat(17);
+        self.state ^= self.state << Seeds::splat(5);
+        self.state
+    }
+
+    /// Uniformly distributed over the whole `Integer` range.
+    pub fn next_integers(&mut self) -> Integers<N> {
+        self.next_seeds().cast::<Integer>()
+    }
+
+    /// Uniformly distributed in `[0, 1)`, from the top 24 bits of `next_integers`.
+    pub fn next_reals(&mut self) -> Reals<N> {
+        (self.next_integers().cast::<u32>() >> Seeds::splat(8)).cast::<Real>()
+            * Reals::splat(1.0 / 16_777_216.0)
+    }
+
+    /// Uniformly distributed in the unit disk in the XY plane.
+    // For lens sampling outside the renderer's stratified patterns, unused so far.
+    #[allow(dead_code)]
+    pub fn next_in_disk(&mut self) -> Points<N> {
+        square_to_disk(self.next_reals(), self.next_reals())
+    }
+
+    /// Uniformly distributed on the unit sphere.
+    pub fn next_on_sphere(&mut self) -> Vectors<N> {
+        let zs = self.next_reals() * Reals::splat(2.0) - Reals::splat(1.0);
+        let radii = (Reals::splat(1.0) - zs * zs).simd_max(zeros()).sqrt();
+        let (sines, cosines) = sin_cos(self.next_reals() * Reals::splat(TAU));
+        Points {
+            xs: cosines * radii,
+            ys: sines * radii,
+            zs,
+        }
+    }
+
+    /// Uniformly distributed on the unit hemispheres around `normals`.
+    // For diffuse surfaces, none of which the scene has yet.
+    #[allow(dead_code)]
+    pub fn next_on_hemisphere(&mut self, normals: Vectors<N>) -> Vectors<N> {
+        let mut dirs = self.next_on_sphere();
+        let below_mask = dirs.dot(normals).simd_lt(zeros());
+        dirs.update_if(below_mask, Points::ZERO - dirs);
+        dirs
+    }
+
+    /// Cosine-weighted on the unit hemispheres around unit `normals`.
+    // Importance sampled counterpart of `next_on_hemisphere`, unused until diffuse surfaces.
+    #[allow(dead_code)]
+    pub fn next_cosine_weighted(&mut self, normals: Vectors<N>) -> Vectors<N> {
+        let dirs = normals + self.next_on_sphere();
+        let lengths = dirs.length();
+        // Degenerate samples opposite to the normal are replaced by the normal itself.
+        let mut result = dirs / lengths.simd_max(Reals::splat(Real::EPSILON));
+        result.update_if(lengths.simd_lt(Reals::splat(Real::EPSILON)), normals);
+        result
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub struct Point(Simd<Real, 4>);
 
@@ -285,6 +533,32 @@ impl Point {
     pub fn dot(&self, rhs: Point) -> Real {
         self.x() * rhs.x() + self.y() * rhs.y() + self.z() * rhs.z()
     }
+
+    pub fn cross(&self, rhs: Point) -> Point {
+        Point::new(
+            self.y() * rhs.z() - self.z() * rhs.y(),
+            self.z() * rhs.x() - self.x() * rhs.z(),
+            self.x() * rhs.y() - self.y() * rhs.x(),
+        )
+    }
+
+    pub fn length(&self) -> Real {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(self) -> Point {
+        self / self.length()
+    }
+
+    /// Component-wise minimum.
+    pub fn min(self, rhs: Point) -> Point {
+        Point(self.0.simd_min(rhs.0))
+    }
+
+    /// Component-wise maximum.
+    pub fn max(self, rhs: Point) -> Point {
+        Point(self.0.simd_max(rhs.0))
+    }
 }
 
 impl Add<Point> for Point {
@@ -315,6 +589,98 @@ impl Div<Real> for Point {
     }
 }
 
+/// 3x3 matrix, stored by rows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix {
+    rows: [Vector; 3],
+}
+
+impl Matrix {
+    pub const IDENTITY: Matrix = Matrix {
+        rows: [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0),
+        ],
+    };
+
+    pub const fn from_rows(x: Vector, y: Vector, z: Vector) -> Matrix {
+        Matrix { rows: [x, y, z] }
+    }
+
+    pub fn from_columns(x: Vector, y: Vector, z: Vector) -> Matrix {
+        Matrix::from_rows(x, y, z).transpose()
+    }
+
+    /// Counterclockwise rotation by `angle` radians when looking against `axis`.
+    pub fn rotation(axis: Axis, angle: Real) -> Matrix {
+        let (sin, cos) = angle.sin_cos();
+        match axis {
+            Axis::XS => Matrix::from_rows(
+                Vector::new(1.0, 0.0, 0.0),
+                Vector::new(0.0, cos, -sin),
+                Vector::new(0.0, sin, cos),
+            ),
+            Axis::YS => Matrix::from_rows(
+                Vector::new(cos, 0.0, sin),
+                Vector::new(0.0, 1.0, 0.0),
+                Vector::new(-sin, 0.0, cos),
+            ),
+            Axis::ZS => Matrix::from_rows(
+                Vector::new(cos, -sin, 0.0),
+                Vector::new(sin, cos, 0.0),
+                Vector::new(0.0, 0.0, 1.0),
+            ),
+        }
+    }
+
+    pub fn column(&self, axis: Axis) -> Vector {
+        Vector::new(
+            self.rows[0].get_axis(axis),
+            self.rows[1].get_axis(axis),
+            self.rows[2].get_axis(axis),
+        )
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        Matrix::from_rows(
+            self.column(Axis::XS),
+            self.column(Axis::YS),
+            self.column(Axis::ZS),
+        )
+    }
+
+    pub fn apply(&self, vector: Vector) -> Vector {
+        Vector::new(
+            self.rows[0].dot(vector),
+            self.rows[1].dot(vector),
+            self.rows[2].dot(vector),
+        )
+    }
+
+    pub fn apply_to_points<const N: usize>(&self, points: Points<N>) -> Points<N>
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        Points {
+            xs: Points::from_single(self.rows[0]).dot(points),
+            ys: Points::from_single(self.rows[1]).dot(points),
+            zs: Points::from_single(self.rows[2]).dot(points),
+        }
+    }
+}
+
+impl Mul<Matrix> for Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: Matrix) -> Self::Output {
+        Matrix::from_columns(
+            self.apply(rhs.column(Axis::XS)),
+            self.apply(rhs.column(Axis::YS)),
+            self.apply(rhs.column(Axis::ZS)),
+        )
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Directions {
     up: bool,
@@ -333,6 +699,11 @@ impl Directions {
         }
     }
 
+    /// Up, down, left and right.
+    pub fn to_array(self) -> [bool; 4] {
+        [self.up, self.down, self.left, self.right]
+    }
+
     pub fn to_vector(self, speed: Real) -> Vector {
         Vector::new(
             speed * self.right as usize as Real + (-speed) * self.left as usize as Real,
@@ -341,3 +712,107 @@ impl Directions {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LANES: usize = 8;
+    // Draws from every stream, enough for every lane to hit the edges of the distributions.
+    const DRAWS: usize = 1000;
+    const EPSILON: Real = 1e-4;
+
+    fn random(sample: usize, frame: usize) -> Random<LANES> {
+        Random::new(
+            Integers::from_array([0, 1, 2, 3, 1000, 1001, 65536, 1 << 20]),
+            sample,
+            frame,
+        )
+    }
+
+    fn normals() -> Vectors<LANES> {
+        let mut normals = Points::ZERO;
+        for lane in 0..LANES {
+            let normal = [
+                Vector::new(1.0, 0.0, 0.0),
+                Vector::new(0.0, -1.0, 0.0),
+                Vector::new(0.6, 0.0, 0.8),
+                Vector::new(1.0, 1.0, 1.0).normalize(),
+            ][lane % 4];
+            normals.xs[lane] = normal.x();
+            normals.ys[lane] = normal.y();
+            normals.zs[lane] = normal.z();
+        }
+        normals
+    }
+
+    #[test]
+    fn same_seeds_give_same_streams() {
+        let (mut first, mut second) = (random(3, 7), random(3, 7));
+        for _ in 0..DRAWS {
+            assert_eq!(first.next_integers(), second.next_integers());
+        }
+    }
+
+    #[test]
+    fn streams_differ_between_pixels_samples_and_frames() {
+        let draws = random(3, 7).next_integers();
+        assert_ne!(random(4, 7).next_integers(), draws);
+        assert_ne!(random(3, 8).next_integers(), draws);
+        for lane in 1..LANES {
+            assert_ne!(draws[lane], draws[lane - 1]);
+        }
+    }
+
+    #[test]
+    fn reals_stay_in_unit_interval() {
+        let mut random = random(0, 0);
+        for _ in 0..DRAWS {
+            let reals = random.next_reals();
+            for lane in 0..LANES {
+                assert!((0.0..1.0).contains(&reals[lane]), "{}", reals[lane]);
+            }
+        }
+    }
+
+    #[test]
+    fn disk_samples_stay_in_disk() {
+        let mut random = random(0, 0);
+        for _ in 0..DRAWS {
+            let points = random.next_in_disk();
+            for lane in 0..LANES {
+                let point = points.lane(lane);
+                assert!(point.length() <= 1.0 + EPSILON, "{:?}", point);
+                assert_eq!(point.z(), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_samples_have_unit_length() {
+        let mut random = random(0, 0);
+        for _ in 0..DRAWS {
+            let dirs = random.next_on_sphere();
+            for lane in 0..LANES {
+                assert!((dirs.lane(lane).length() - 1.0).abs() < EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn hemisphere_samples_have_unit_length_around_normals() {
+        let (mut random, normals) = (random(0, 0), normals());
+        for _ in 0..DRAWS {
+            for dirs in [
+                random.next_on_hemisphere(normals),
+                random.next_cosine_weighted(normals),
+            ] {
+                for lane in 0..LANES {
+                    let dir = dirs.lane(lane);
+                    assert!((dir.length() - 1.0).abs() < EPSILON, "{:?}", dir);
+                    assert!(dir.dot(normals.lane(lane)) >= -EPSILON, "{:?}", dir);
+                }
+            }
+        }
+    }
+}
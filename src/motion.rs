@@ -33,6 +33,7 @@ impl MotionTicker {
         near_paddle_directions: Directions,
         far_paddle_directions: Directions,
     ) -> MotionResult {
+        scene.remember_positions();
         let new_ball_pos = scene.sphere_pos(Sphere::Ball) + self.ball_speed * elapsed;
         scene.move_sphere_to(Sphere::Ball, new_ball_pos);
         Self::move_paddle(
@@ -77,6 +78,45 @@ impl MotionTicker {
         MotionResult::NoCollision
     }
 
+    pub fn ball_speed(&self) -> Vector {
+        self.ball_speed
+    }
+
+    /// Where the ball's center will be once it gets as close to the near plane as the front of
+    /// the near paddle lets it, bouncing off the walls on the way, `None` while it moves away.
+    /// The paddles and the curve of the near one's front are left out.
+    pub fn predict_intercept(&self, scene: &Scene) -> Option<Point> {
+        let ball_pos = scene.sphere_pos(Sphere::Ball);
+        let radius = scene.sphere_radius(Sphere::Ball);
+        let intercept_z = scene.sphere_pos(Sphere::NearPaddle).z()
+            - scene.sphere_radius(Sphere::NearPaddle)
+            - radius;
+        if self.ball_speed.z() <= 0.0 || ball_pos.z() >= intercept_z {
+            return None;
+        }
+
+        let time = (intercept_z - ball_pos.z()) / self.ball_speed.z();
+        let mut intercept = ball_pos + self.ball_speed * time;
+        *intercept.z_mut() = intercept_z;
+        for (axis, min_plane, max_plane) in [
+            (Axis::XS, Plane::Left, Plane::Right),
+            (Axis::YS, Plane::Bottom, Plane::Top),
+        ] {
+            let min = scene.plane_offset(min_plane) + radius;
+            let span = scene.plane_offset(max_plane) - radius - min;
+            // Every bounce mirrors the rest of the way, so the straight path folds back and
+            // forth between the walls.
+            let offset = (intercept.get_axis(axis) - min).rem_euclid(2.0 * span);
+            *intercept.get_axis_mut(axis) = min
+                + if offset > span {
+                    2.0 * span - offset
+                } else {
+                    offset
+                };
+        }
+        Some(intercept)
+    }
+
     fn move_paddle(
         scene: &mut Scene,
         elapsed: Real,
@@ -0,0 +1,43 @@
+//! Settings picked by name on the command line and cycled through in the menus.
+
+/// Declares a fieldless enum with a name for every variant, along with `name`, `from_name`
+/// and `next`, which cycles through the variants in their order.
+macro_rules! named_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $enum:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident => $name:literal,
+            )+
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $enum {
+            $(
+                $(#[$variant_meta])*
+                $variant,
+            )+
+        }
+
+        impl $enum {
+            const VARIANTS: &'static [$enum] = &[$($enum::$variant),+];
+            const NAMES: &'static [&'static str] = &[$($name),+];
+
+            pub fn name(&self) -> &'static str {
+                Self::NAMES[*self as usize]
+            }
+
+            pub fn from_name(name: &str) -> Option<$enum> {
+                Self::NAMES
+                    .iter()
+                    .position(|&variant_name| variant_name == name)
+                    .map(|i| Self::VARIANTS[i])
+            }
+
+            pub fn next(&self) -> $enum {
+                Self::VARIANTS[(*self as usize + 1) % Self::VARIANTS.len()]
+            }
+        }
+    };
+}
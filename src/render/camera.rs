@@ -1,23 +1,61 @@
-use crate::math::{Point, Points, Rays, Real, Reals, Vector};
+use crate::math::{
+    Axis, LaneCount, Matrix, Point, Points, Rays, Real, Reals, SupportedLaneCount, Vector,
+};
 
+// Rays start this far along their directions, just past the near paddle.
+const NEAR_OFFSET: Real = 0.91;
+// Shorter vectors have no direction `look_at` can trust.
+const LOOK_AT_EPSILON: Real = 1e-6;
+
+/// Looks down its local -Z axis with local +Y up, `orientation` maps local axes to the world.
+#[derive(Clone)]
 pub struct Camera {
     origin: Point,
-    view_port_base: Point,
+    orientation: Matrix,
+    roll: Real,
+    fov: Real,
+    aspect_ratio: Real,
+    aperture: Real,
+    focus_distance: Real,
+    // Sideways shift of the view port, in its widths, making stereo eyes converge.
+    view_port_shift: Real,
+    view_port_base: Vector,
     view_port_x_axis: Vector,
     view_port_y_axis: Vector,
+    view_orientation: Matrix,
+    forward: Vector,
 }
 
 impl Camera {
-    pub fn new(aspect_ratio: f32, width: f32) -> Camera {
-        let view_port_width = width;
-        let view_port_height = view_port_width / aspect_ratio;
-
-        Camera {
+    /// `fov` is the horizontal field of view in radians.
+    pub fn new(aspect_ratio: Real, fov: Real) -> Camera {
+        let mut camera = Camera {
             origin: Point::new(0.0, 0.0, 0.9),
-            view_port_base: Point::new(-view_port_width / 2.0, view_port_height / 2.0, -1.0),
-            view_port_x_axis: Vector::new(view_port_width, 0.0, 0.0),
-            view_port_y_axis: Vector::new(0.0, -view_port_height, 0.0),
-        }
+            orientation: Matrix::IDENTITY,
+            roll: 0.0,
+            fov,
+            aspect_ratio,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            view_port_shift: 0.0,
+            view_port_base: Vector::new(0.0, 0.0, 0.0),
+            view_port_x_axis: Vector::new(0.0, 0.0, 0.0),
+            view_port_y_axis: Vector::new(0.0, 0.0, 0.0),
+            view_orientation: Matrix::IDENTITY,
+            forward: Vector::new(0.0, 0.0, -1.0),
+        };
+        camera.update_view_port();
+        camera
+    }
+
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    // Counterpart of `set_orientation` for camera controllers, none reads it back yet.
+    #[allow(dead_code)]
+    pub fn orientation(&self) -> Matrix {
+        self.orientation
     }
 
     pub fn move_origin_to(&mut self, x: Real, y: Real) {
@@ -25,11 +63,182 @@ impl Camera {
         *self.origin.y_mut() = y;
     }
 
-    pub fn pixel_rays(&self, x_offsets: Reals, y_offsets: Reals) -> Rays {
+    pub fn move_to(&mut self, origin: Point) {
+        self.origin = origin;
+    }
+
+    pub fn set_orientation(&mut self, orientation: Matrix) {
+        self.orientation = orientation;
+        self.update_view_port();
+    }
+
+    /// Turns the camera towards `target`, keeping `up` above the horizon. A `target` at the
+    /// origin leaves the camera as it is, and an `up` along the view picks any roll.
+    // Nothing aims the camera at points yet, the game camera looks straight down the room.
+    #[allow(dead_code)]
+    pub fn look_at(&mut self, target: Point, up: Vector) {
+        let to_target = target - self.origin;
+        if to_target.length() < LOOK_AT_EPSILON {
+            return;
+        }
+        let forward = to_target.normalize();
+        let mut right = forward.cross(up);
+        if right.length() < LOOK_AT_EPSILON {
+            let across = if forward.x().abs() < 0.5 {
+                Vector::new(1.0, 0.0, 0.0)
+            } else {
+                Vector::new(0.0, 0.0, 1.0)
+            };
+            right = forward.cross(across);
+        }
+        let right = right.normalize();
+        let up = right.cross(forward);
+        self.set_orientation(Matrix::from_columns(
+            right,
+            up,
+            Vector::new(0.0, 0.0, 0.0) - forward,
+        ));
+    }
+
+    /// Rotation in radians around the view direction, applied on top of the orientation.
+    // No controller rolls the camera yet.
+    #[allow(dead_code)]
+    pub fn set_roll(&mut self, roll: Real) {
+        self.roll = roll;
+        self.update_view_port();
+    }
+
+    pub fn set_fov(&mut self, fov: Real) {
+        self.fov = fov;
+        self.update_view_port();
+    }
+
+    pub fn aspect_ratio(&self) -> Real {
+        self.aspect_ratio
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Real) {
+        self.aspect_ratio = aspect_ratio;
+        self.update_view_port();
+    }
+
+    /// Lens radius, `0.0` makes it a pinhole camera with everything in focus.
+    pub fn set_aperture(&mut self, aperture: Real) {
+        self.aperture = aperture;
+    }
+
+    /// Puts the plane of focus at the depth of `point`.
+    pub fn focus_on(&mut self, point: Point) {
+        self.focus_distance = (point - self.origin).dot(self.forward).max(NEAR_OFFSET);
+    }
+
+    /// Copy of the camera moved `offset` to its right, for a stereo eye, with its view shifted
+    /// back so both eyes see what's at the focus distance at the same place.
+    pub fn eye(&self, offset: Real) -> Camera {
+        let mut eye = self.clone();
+        let right = self.view_orientation.column(Axis::XS);
+        eye.origin = self.origin + right * offset;
+        let view_port_width = (self.fov / 2.0).tan() * 2.0;
+        eye.view_port_shift = offset / self.focus_distance / view_port_width;
+        eye.update_view_port();
+        eye
+    }
+
+    fn update_view_port(&mut self) {
+        let view_port_width = (self.fov / 2.0).tan() * 2.0;
+        let view_port_height = view_port_width / self.aspect_ratio;
+        self.view_orientation = self.orientation * Matrix::rotation(Axis::ZS, self.roll);
+
+        let right = self.view_orientation.column(Axis::XS);
+        let up = self.view_orientation.column(Axis::YS);
+        self.forward = Vector::new(0.0, 0.0, 0.0) - self.view_orientation.column(Axis::ZS);
+        self.view_port_base = self.forward
+            - right * (view_port_width * (0.5 + self.view_port_shift))
+            + up * (view_port_height / 2.0);
+        self.view_port_x_axis = right * view_port_width;
+        self.view_port_y_axis = up * -view_port_height;
+    }
+
+    /// Image offsets of `point` in the same `[0, 1]` units `pixel_rays` takes, `None` for points
+    /// behind the camera.
+    pub fn project(&self, point: Point) -> Option<(Real, Real)> {
+        let local = self.view_orientation.transpose().apply(point - self.origin);
+        let depth = -local.z();
+        if depth <= 0.0 {
+            return None;
+        }
+        let view_port_width = (self.fov / 2.0).tan() * 2.0;
+        let view_port_height = view_port_width / self.aspect_ratio;
+        Some((
+            local.x() / depth / view_port_width + 0.5 + self.view_port_shift,
+            0.5 - local.y() / depth / view_port_height,
+        ))
+    }
+
+    /// `lens_offsets` are points of the unit disk in the XY plane, ignored for pinhole cameras.
+    #[inline(always)]
+    pub fn pixel_rays<const N: usize>(
+        &self,
+        x_offsets: Reals<N>,
+        y_offsets: Reals<N>,
+        lens_offsets: Points<N>,
+    ) -> Rays<N>
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
         let mut dirs = Points::from_single(self.view_port_base);
         dirs += Points::from_single(self.view_port_x_axis) * x_offsets;
         dirs += Points::from_single(self.view_port_y_axis) * y_offsets;
-        let origins = Points::from_single(self.origin) + dirs * 0.91;
+        let mut origins = Points::from_single(self.origin);
+        if self.aperture > 0.0 {
+            let lens_shifts = self
+                .view_orientation
+                .apply_to_points(lens_offsets * self.aperture);
+            // Directions are one unit deep, so every ray still meets the pinhole one at the
+            // focus distance.
+            dirs -= lens_shifts * (1.0 / self.focus_distance);
+            origins += lens_shifts;
+        }
+        origins += dirs * NEAR_OFFSET;
         Rays { origins, dirs }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_finite(camera: &Camera) {
+        for axis in [Axis::XS, Axis::YS, Axis::ZS] {
+            let column = camera.orientation().column(axis);
+            assert!((column.length() - 1.0).abs() < 1e-4, "{:?}", column);
+        }
+    }
+
+    #[test]
+    fn look_at_faces_target() {
+        let mut camera = Camera::new(1.0, 1.0);
+        let target = Point::new(3.0, -1.0, -4.0);
+        camera.look_at(target, Vector::new(0.0, 1.0, 0.0));
+        assert_finite(&camera);
+        let forward = Vector::new(0.0, 0.0, 0.0) - camera.orientation().column(Axis::ZS);
+        assert!(forward.dot((target - camera.origin()).normalize()) > 1.0 - 1e-4);
+    }
+
+    #[test]
+    fn look_at_along_up_stays_finite() {
+        let mut camera = Camera::new(1.0, 1.0);
+        for up in [Vector::new(0.0, 1.0, 0.0), Vector::new(1.0, 0.0, 0.0)] {
+            camera.look_at(camera.origin() + up * 2.0, up);
+            assert_finite(&camera);
+        }
+    }
+
+    #[test]
+    fn look_at_origin_keeps_orientation() {
+        let mut camera = Camera::new(1.0, 1.0);
+        camera.look_at(camera.origin(), Vector::new(0.0, 1.0, 0.0));
+        assert_finite(&camera);
+        assert_eq!(camera.orientation().column(Axis::ZS).z(), 1.0);
+    }
+}
@@ -0,0 +1,141 @@
+//! Emulation of a CRT screen over the encoded frame: its curvature, scanlines, phosphor mask
+//! and the glow of its bright parts.
+
+use crate::math::Real;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+
+// Strength of the curvature, how much further from the center corners get.
+const BARREL: Real = 0.05;
+// Every other line gets this much darker.
+const SCANLINE_DARKENING: Real = 0.3;
+// Channels get this much darker outside of the columns of their phosphors.
+const MASK_DARKENING: Real = 0.25;
+// Light pixels spread around this many pixels on their line, adding this much of their light.
+const BLOOM_RADIUS: usize = 2;
+const BLOOM_STRENGTH: Real = 0.2;
+// Makes up for some of the light scanlines and the mask take away, the bloom doing the rest
+// in bright areas.
+const BRIGHTNESS: Real = 1.15;
+
+/// Applies the filter over frames of the same size, only computing the curvature when it
+/// changes.
+#[derive(Default)]
+pub struct CrtFilter {
+    width: usize,
+    height: usize,
+    // Pixel of the flat frame every pixel of the curved one shows, none past the screen edges.
+    sources: Vec<Option<u32>>,
+    // Copy of the frame being filtered, kept around to reuse its allocation.
+    frame: Vec<[u8; 4]>,
+}
+
+impl CrtFilter {
+    /// Filters the RGB of every pixel of `buffer`, leaving alpha alone.
+    pub fn apply(&mut self, buffer: &mut [[u8; 4]], width: usize) {
+        let height = buffer.len() / width;
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.sources = curved_sources(width, height);
+        }
+        self.frame.resize(buffer.len(), [0; 4]);
+
+        // Glows once along every line, for the curvature to sample from.
+        #[cfg(not(target_arch = "wasm32"))]
+        let lines = self
+            .frame
+            .par_chunks_mut(width)
+            .zip(buffer.par_chunks(width));
+        #[cfg(target_arch = "wasm32")]
+        let lines = self.frame.chunks_mut(width).zip(buffer.chunks(width));
+        lines.for_each(|(glowing, line)| bloom_line(line, glowing));
+
+        // Light kept by every channel on even and odd lines, over the 3 columns of the mask, in
+        // 256ths.
+        let mut factors = [[[0u32; 3]; 3]; 2];
+        for (parity, factors) in factors.iter_mut().enumerate() {
+            let scanline = BRIGHTNESS * (1.0 - SCANLINE_DARKENING * parity as Real);
+            for (column, factors) in factors.iter_mut().enumerate() {
+                for (channel, factor) in factors.iter_mut().enumerate() {
+                    let mask = if column == channel {
+                        1.0
+                    } else {
+                        1.0 - MASK_DARKENING
+                    };
+                    *factor = (scanline * mask * 256.0) as u32;
+                }
+            }
+        }
+
+        let (frame, sources) = (&self.frame, &self.sources);
+        #[cfg(not(target_arch = "wasm32"))]
+        let lines = buffer.par_chunks_mut(width);
+        #[cfg(target_arch = "wasm32")]
+        let lines = buffer.chunks_mut(width);
+        lines.enumerate().for_each(|(y, line)| {
+            let factors = &factors[y % 2];
+            let sources = &sources[y * width..(y + 1) * width];
+            for (x, (pixel, source)) in line.iter_mut().zip(sources).enumerate() {
+                let source = match source {
+                    Some(source) => &frame[*source as usize],
+                    None => {
+                        pixel[..3].fill(0);
+                        continue;
+                    }
+                };
+                for ((output, &light), &factor) in pixel.iter_mut().zip(source).zip(&factors[x % 3])
+                {
+                    *output = ((light as u32 * factor) >> 8).min(255) as u8;
+                }
+            }
+        });
+    }
+}
+
+/// Adds to every pixel of `line` the glow of the ones around, repeating the ends.
+fn bloom_line(line: &[[u8; 4]], glowing: &mut [[u8; 4]]) {
+    let last = line.len() - 1;
+    let at = |i: isize| line[(i.max(0) as usize).min(last)];
+    let radius = BLOOM_RADIUS as isize;
+    let mut sums = [0u32; 3];
+    for i in -radius..=radius {
+        for (sum, &channel) in sums.iter_mut().zip(&at(i)) {
+            *sum += channel as u32;
+        }
+    }
+    let scale = BLOOM_STRENGTH / ((2 * radius + 1) * (2 * radius + 1)) as Real / 255.0;
+    for (x, (glowing, pixel)) in glowing.iter_mut().zip(line).enumerate() {
+        for channel in 0..3 {
+            let sum = sums[channel] as Real;
+            glowing[channel] = (pixel[channel] as Real + sum * sum * scale).min(255.0) as u8;
+        }
+        let x = x as isize;
+        let (entering, leaving) = (at(x + radius + 1), at(x - radius));
+        for channel in 0..3 {
+            sums[channel] = sums[channel] + entering[channel] as u32 - leaving[channel] as u32;
+        }
+    }
+}
+
+/// Barrel distortion mapping, the middles of the edges staying on the edges and the corners
+/// getting rounded off.
+fn curved_sources(width: usize, height: usize) -> Vec<Option<u32>> {
+    let mut sources = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let v = (y as Real + 0.5) / height as Real * 2.0 - 1.0;
+        for x in 0..width {
+            let u = (x as Real + 0.5) / width as Real * 2.0 - 1.0;
+            let scale = (1.0 + BARREL * (u * u + v * v)) / (1.0 + BARREL);
+            let (u, v) = (u * scale, v * scale);
+            sources.push(if u.abs() < 1.0 && v.abs() < 1.0 {
+                let x = ((u + 1.0) / 2.0 * width as Real) as usize;
+                let y = ((v + 1.0) / 2.0 * height as Real) as usize;
+                Some((y.min(height - 1) * width + x.min(width - 1)) as u32)
+            } else {
+                None
+            });
+        }
+    }
+    sources
+}
@@ -1,3 +1,7 @@
 pub mod camera;
+pub mod crt;
+pub mod post;
 pub mod renderer;
+pub mod spectrum;
+pub mod stats;
 pub mod tracer;
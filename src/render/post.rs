@@ -0,0 +1,316 @@
+//! Passes over the linear HDR frame the tracer outputs, run before quantizing it to 8 bits.
+
+use crate::math::{Color, Integer, Integers, Random, Real};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+
+// Film grain draws this many pixels' noise at once, from a stream no sample of the tracer uses.
+const GRAIN_LANES: usize = 4;
+const GRAIN_STREAM: usize = usize::MAX;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PostPass {
+    /// Spreads the light above `threshold` around, over `radius` times the frame height.
+    Bloom {
+        threshold: Real,
+        strength: Real,
+        radius: Real,
+    },
+    /// Darkens towards the corners, by `strength` in the corners themselves.
+    Vignette { strength: Real },
+    /// Samples red further from the center and blue closer to it, by `spread` times the
+    /// distance.
+    ChromaticAberration { spread: Real },
+    /// Noise changing every frame, scaling pixels by up to `strength` either way.
+    FilmGrain { strength: Real },
+}
+
+/// Passes with their default settings, in the order they'd better run in.
+pub const DEFAULT_POST_PASSES: [PostPass; 4] = [
+    PostPass::Bloom {
+        threshold: 1.0,
+        strength: 0.4,
+        radius: 0.015,
+    },
+    PostPass::ChromaticAberration { spread: 0.004 },
+    PostPass::Vignette { strength: 0.35 },
+    PostPass::FilmGrain { strength: 0.06 },
+];
+const DEFAULT_POST_PASSES_NAMES: [&str; 4] =
+    ["bloom", "chromatic-aberration", "vignette", "film-grain"];
+
+impl PostPass {
+    /// Default settings of the pass named `name`.
+    pub fn from_name(name: &str) -> Option<PostPass> {
+        DEFAULT_POST_PASSES_NAMES
+            .iter()
+            .position(|&pass_name| pass_name == name)
+            .map(|i| DEFAULT_POST_PASSES[i])
+    }
+
+    pub fn apply(&self, pixels: &mut [Color], width: usize, frame: usize) {
+        match *self {
+            PostPass::Bloom {
+                threshold,
+                strength,
+                radius,
+            } => bloom(pixels, width, threshold, strength, radius),
+            PostPass::Vignette { strength } => {
+                let height = pixels.len() / width;
+                for_each_line(pixels, width, |y, line| {
+                    for (x, pixel) in line.iter_mut().enumerate() {
+                        let (dx, dy) = from_center(x, y, width, height);
+                        // Corners are at a squared distance of 2.
+                        *pixel = *pixel * (1.0 - strength * (dx * dx + dy * dy) / 2.0);
+                    }
+                });
+            }
+            PostPass::ChromaticAberration { spread } => {
+                let source = pixels.to_vec();
+                let height = pixels.len() / width;
+                // Sampled coordinates only depend on the one they're sampled for.
+                let scaled = |size: usize, scale: Real| -> Vec<usize> {
+                    let center = (size - 1) as Real / 2.0;
+                    (0..size)
+                        .map(|i| {
+                            let i = (center + (i as Real - center) * scale).round();
+                            (i.max(0.0) as usize).min(size - 1)
+                        })
+                        .collect()
+                };
+                let (red_xs, red_ys) = (scaled(width, 1.0 + spread), scaled(height, 1.0 + spread));
+                let (blue_xs, blue_ys) =
+                    (scaled(width, 1.0 - spread), scaled(height, 1.0 - spread));
+                for_each_line(pixels, width, |y, line| {
+                    let red_line = &source[red_ys[y] * width..(red_ys[y] + 1) * width];
+                    let blue_line = &source[blue_ys[y] * width..(blue_ys[y] + 1) * width];
+                    for (x, pixel) in line.iter_mut().enumerate() {
+                        let red = red_line[red_xs[x]];
+                        let blue = blue_line[blue_xs[x]];
+                        *pixel = Color::new(red.x(), pixel.y(), blue.z());
+                    }
+                });
+            }
+            PostPass::FilmGrain { strength } => {
+                for_each_line(pixels, width, |y, line| {
+                    for (chunk, pixels) in line.chunks_mut(GRAIN_LANES).enumerate() {
+                        let mut pixel_ids = Integers::<GRAIN_LANES>::splat(0);
+                        for i in 0..GRAIN_LANES {
+                            pixel_ids[i] = (y * width + chunk * GRAIN_LANES + i) as Integer;
+                        }
+                        let noise = Random::new(pixel_ids, GRAIN_STREAM, frame).next_reals();
+                        for (i, pixel) in pixels.iter_mut().enumerate() {
+                            *pixel = *pixel * (1.0 + strength * (noise[i] * 2.0 - 1.0));
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Runs `f` on every line of the frame along with its index, on all threads.
+fn for_each_line(
+    pixels: &mut [Color],
+    width: usize,
+    f: impl Fn(usize, &mut [Color]) + Send + Sync,
+) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let lines = pixels.par_chunks_mut(width);
+    #[cfg(target_arch = "wasm32")]
+    let lines = pixels.chunks_mut(width);
+    lines.enumerate().for_each(|(y, line)| f(y, line));
+}
+
+/// Offsets of the center of the pixel from the center of the frame, `-1` to `1` across it.
+fn from_center(x: usize, y: usize, width: usize, height: usize) -> (Real, Real) {
+    (
+        (x as Real + 0.5) / width as Real * 2.0 - 1.0,
+        (y as Real + 0.5) / height as Real * 2.0 - 1.0,
+    )
+}
+
+/// Blurs what is above `threshold` at half the resolution, which is plenty for a glow, and
+/// adds it back.
+fn bloom(pixels: &mut [Color], width: usize, threshold: Real, strength: Real, radius: Real) {
+    let height = pixels.len() / width;
+    let (half_width, half_height) = (width / 2 + width % 2, height / 2 + height % 2);
+    let radius = ((radius * half_height as Real) as usize).max(1);
+    let zero = Color::new(0.0, 0.0, 0.0);
+    let threshold = Color::new(threshold, threshold, threshold);
+    let bright = |x: usize, y: usize| {
+        (pixels[y.min(height - 1) * width + x.min(width - 1)] - threshold).max(zero)
+    };
+
+    let mut glow = vec![zero; half_width * half_height];
+    for_each_line(&mut glow, half_width, |y, line| {
+        for (x, glow) in line.iter_mut().enumerate() {
+            let (x, y) = (x * 2, y * 2);
+            *glow =
+                (bright(x, y) + bright(x + 1, y) + bright(x, y + 1) + bright(x + 1, y + 1)) * 0.25;
+        }
+    });
+    // Two box blurs add up to a tent, smooth enough for a glow.
+    for _ in 0..2 {
+        for_each_line(&mut glow, half_width, |_, line| {
+            let source = line.to_vec();
+            box_blur_line(&source, line, radius);
+        });
+        box_blur_columns(&mut glow, half_width, radius);
+    }
+
+    // Bilinear upsampling, full resolution pixel centers fall a quarter of the way between
+    // half resolution ones.
+    let glow_at =
+        |x: usize, y: usize| glow[y.min(half_height - 1) * half_width + x.min(half_width - 1)];
+    for_each_line(pixels, width, |y, line| {
+        let (glow_y, y_weight) = upsampled(y);
+        for (x, pixel) in line.iter_mut().enumerate() {
+            let (glow_x, x_weight) = upsampled(x);
+            let top =
+                glow_at(glow_x, glow_y) * (1.0 - x_weight) + glow_at(glow_x + 1, glow_y) * x_weight;
+            let bottom = glow_at(glow_x, glow_y + 1) * (1.0 - x_weight)
+                + glow_at(glow_x + 1, glow_y + 1) * x_weight;
+            *pixel = *pixel + (top * (1.0 - y_weight) + bottom * y_weight) * strength;
+        }
+    });
+}
+
+/// Half resolution coordinate before the full resolution `i` and the weight of the one after.
+fn upsampled(i: usize) -> (usize, Real) {
+    match i {
+        0 => (0, 0.0),
+        _ => ((i - 1) / 2, if i % 2 == 1 { 0.25 } else { 0.75 }),
+    }
+}
+
+/// Averages of `2 * radius + 1` pixels around every pixel of `source`, repeating the ends.
+fn box_blur_line(source: &[Color], blurred: &mut [Color], radius: usize) {
+    let last = source.len() - 1;
+    let at = |i: isize| source[(i.max(0) as usize).min(last)];
+    let radius = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as Real;
+    let mut sum = (-radius..=radius).fold(Color::new(0.0, 0.0, 0.0), |sum, i| sum + at(i));
+    for (x, pixel) in blurred.iter_mut().enumerate() {
+        *pixel = sum * scale;
+        let x = x as isize;
+        sum = sum + at(x + radius + 1) - at(x - radius);
+    }
+}
+
+/// `box_blur_line` down every column, keeping the running sums of a whole line at once.
+fn box_blur_columns(pixels: &mut [Color], width: usize, radius: usize) {
+    let source = pixels.to_vec();
+    let last = source.len() / width - 1;
+    let line = |y: isize| {
+        let y = (y.max(0) as usize).min(last);
+        &source[y * width..(y + 1) * width]
+    };
+    let radius = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as Real;
+    let mut sums = vec![Color::new(0.0, 0.0, 0.0); width];
+    for y in -radius..=radius {
+        for (sum, &pixel) in sums.iter_mut().zip(line(y)) {
+            *sum = *sum + pixel;
+        }
+    }
+    for (y, blurred) in pixels.chunks_mut(width).enumerate() {
+        let y = y as isize;
+        for (((pixel, sum), &entering), &leaving) in blurred
+            .iter_mut()
+            .zip(&mut sums)
+            .zip(line(y + radius + 1))
+            .zip(line(y - radius))
+        {
+            *pixel = *sum * scale;
+            *sum = *sum + entering - leaving;
+        }
+    }
+}
+
+named_enum! {
+    /// Curves compressing the unbounded light of the frame into what a display can show.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum ToneMapping {
+        /// Clips everything above 1.
+        Clamp => "clamp",
+        /// `x / (1 + x)`, never clips but flattens the highlights a lot.
+        Reinhard => "reinhard",
+        /// Narkowicz's fit of the ACES reference rendering transform, contrasty and saturated.
+        Aces => "aces",
+        /// Hable's curve from Uncharted 2, with a softer toe and shoulder.
+        Filmic => "filmic",
+    }
+}
+
+impl ToneMapping {
+    /// Maps a channel of linear light to a linear display value between 0 and 1.
+    fn map(&self, x: Real) -> Real {
+        let x = x.max(0.0);
+        match self {
+            ToneMapping::Clamp => x.min(1.0),
+            ToneMapping::Reinhard => x / (1.0 + x),
+            ToneMapping::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.0)
+            }
+            ToneMapping::Filmic => {
+                // The curve is meant for light twice as bright, and reaches 1 at a white of 11.2.
+                let curve = |x: Real| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                (curve(2.0 * x) / curve(11.2)).min(1.0)
+            }
+        }
+    }
+}
+
+// Linear values index the sRGB encoding table in steps this small, fine enough that the
+// steep start of the curve doesn't skip any 8 bit level.
+const SRGB_TABLE_SIZE: usize = 1 << 14;
+const SRGB_TABLE_LAST: Real = (SRGB_TABLE_SIZE - 1) as Real;
+
+lazy_static! {
+    /// The sRGB transfer function quantized to 8 bits, built once as it takes a `powf` per
+    /// entry.
+    static ref SRGB_TABLE: Vec<u8> = (0..SRGB_TABLE_SIZE)
+        .map(|i| {
+            let x = i as Real / SRGB_TABLE_LAST;
+            let encoded = if x <= 0.0031308 {
+                x * 12.92
+            } else {
+                1.055 * x.powf(1.0 / 2.4) - 0.055
+            };
+            (encoded * 255.0).round() as u8
+        })
+        .collect();
+}
+
+/// Quantizes the frame to 8 bits per channel, leaving alpha alone. Light is scaled by
+/// `exposure`, compressed by `tone_mapping` and dimmed by `coef` before being sRGB encoded,
+/// while debug values, without a tone mapping, are just dimmed and clamped.
+pub fn encode(
+    pixels: &[Color],
+    exposure: Real,
+    tone_mapping: Option<ToneMapping>,
+    coef: Real,
+    buffer: &mut [[u8; 4]],
+) {
+    let srgb_table = &*SRGB_TABLE;
+    #[cfg(not(target_arch = "wasm32"))]
+    let pairs = buffer.par_iter_mut().zip(pixels);
+    #[cfg(target_arch = "wasm32")]
+    let pairs = buffer.iter_mut().zip(pixels);
+    pairs.for_each(|(output, &pixel)| {
+        let channels = [pixel.x(), pixel.y(), pixel.z()];
+        for (output, channel) in output.iter_mut().zip(channels) {
+            *output = match tone_mapping {
+                Some(tone_mapping) => {
+                    let display = (tone_mapping.map(channel * exposure) * coef).min(1.0);
+                    srgb_table[(display * SRGB_TABLE_LAST).round() as usize]
+                }
+                None => ((channel * coef).clamp(0.0, 1.0) * 255.0) as u8,
+            };
+        }
+    });
+}
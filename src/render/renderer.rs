@@ -1,88 +1,864 @@
 use crate::{
-    math::{Reals, LANES, ZEROS, ZERO_POINTS},
+    math::{
+        square_to_disk, zeros, Color, Colors, Integer, Integers, LaneCount, Mask, Point, Points,
+        Random, Real, Reals, SimdLevel, SimdPartialOrd, StdFloat, SupportedLaneCount,
+        SUPPORTED_LANES,
+    },
     render::camera::Camera,
-    render::tracer::trace_rays,
+    render::post::{encode, PostPass, ToneMapping},
+    render::spectrum::Palette,
+    render::stats::{RayStats, TraceCounters},
+    render::tracer::{previous_positions, trace_rays, RenderMode, TraceOptions},
     scene::Scene,
 };
-
 #[cfg(not(target_arch = "wasm32"))]
 use rayon::prelude::*;
 
+// Tiles are this many pixels wide and high, a multiple of every packet size.
+const TILE_SIZE: usize = 8;
+// Pixels with a channel this much off a neighbour's after gamma get refined by adaptive
+// sampling.
+const EDGE_THRESHOLD: Real = 0.125;
+// Distance between the eyes of stereo views, twice a person's if the room is 4 meters wide,
+// making depth stand out more.
+const EYE_SEPARATION: Real = 0.13;
+
 pub struct Renderer {
     width: f32,
     height: f32,
-    lanes_per_line: usize,
-    samples_per_pixel: usize,
     max_depth: usize,
-    x_deltas: Vec<Reals>,
-    y_deltas: Vec<Reals>,
+    shutter: f32,
+    mode: RenderMode,
+    collect_stats: bool,
+    stats: RayStats,
+    simd_level: SimdLevel,
+    lanes: usize,
+    samples: SamplePattern,
+    edge_samples: Option<SamplePattern>,
+    interlacing: Interlacing,
+    stereo: Stereo,
+    // One per view, of the eyes in stereo.
+    previous_frames: Vec<PreviousFrame>,
+    post_passes: Vec<PostPass>,
+    exposure: Real,
+    tone_mapping: ToneMapping,
+    palette: Palette,
+    // Linear light of the frame being rendered and of every view it's composed of, kept around
+    // to reuse their allocations.
+    hdr_pixels: Vec<Color>,
+    views: Vec<Vec<Color>>,
+}
+
+named_enum! {
+    /// Which pixels get traced each frame, the others are reprojected from the previous one.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Interlacing {
+        Off => "off",
+        Rows => "rows",
+        Checkerboard => "checkerboard",
+    }
+}
+
+impl Interlacing {
+    /// Even frames trace the even rows or the black squares, odd ones the rest.
+    fn traces(self, x: usize, y: usize, frame: usize) -> bool {
+        match self {
+            Interlacing::Off => true,
+            Interlacing::Rows => y % 2 == frame % 2,
+            Interlacing::Checkerboard => x % 2 == (y + frame) % 2,
+        }
+    }
+
+    /// Columns and rows of the pixels a tile traces per frame.
+    fn tile_grid(self) -> (usize, usize) {
+        match self {
+            Interlacing::Off => (TILE_SIZE, TILE_SIZE),
+            Interlacing::Rows => (TILE_SIZE, TILE_SIZE / 2),
+            Interlacing::Checkerboard => (TILE_SIZE / 2, TILE_SIZE),
+        }
+    }
+
+    /// Position within its tile of the pixel at `column` and `row` of the tile grid, tiles
+    /// start on even coordinates so it traces the same pixels as `traces`.
+    fn tile_offset(self, column: usize, row: usize, frame: usize) -> (usize, usize) {
+        match self {
+            Interlacing::Off => (column, row),
+            Interlacing::Rows => (column, row * 2 + frame % 2),
+            Interlacing::Checkerboard => (column * 2 + (row + frame) % 2, row),
+        }
+    }
+}
+
+named_enum! {
+    /// Views the frame is composed of, from a camera per eye, for glasses or viewers making the
+    /// depth of the room visible.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Stereo {
+        Off => "off",
+        /// Red for the left eye, green and blue for the right one, for red/cyan glasses.
+        Anaglyph => "anaglyph",
+        /// The left eye's view on the left half of the frame and the right one's on the right
+        /// half, for cardboard viewers.
+        SideBySide => "side-by-side",
+    }
+}
+
+impl Stereo {
+    /// What frames `width` pixels wide can show, side by side views taking a pixel at least
+    /// each.
+    fn fitting(self, width: usize) -> Stereo {
+        match self {
+            Stereo::SideBySide if width < 2 => Stereo::Off,
+            stereo => stereo,
+        }
+    }
+
+    /// Offset of the eye to the right of the camera and width of every view of a frame `width`
+    /// pixels wide.
+    fn views(&self, width: usize) -> Vec<(Real, usize)> {
+        let eye = EYE_SEPARATION / 2.0;
+        match self {
+            Stereo::Off => vec![(0.0, width)],
+            Stereo::Anaglyph => vec![(-eye, width), (eye, width)],
+            Stereo::SideBySide => vec![(-eye, width / 2), (eye, width - width / 2)],
+        }
+    }
+}
+
+/// What interlaced frames reproject their missing pixels from.
+struct PreviousFrame {
+    camera: Camera,
+    pixels: Vec<Color>,
+}
+
+/// Sub-pixel and lens positions of every sample of a pixel.
+struct SamplePattern {
+    x_deltas: Vec<Real>,
+    y_deltas: Vec<Real>,
+    lens_x_deltas: Vec<Real>,
+    lens_y_deltas: Vec<Real>,
+}
+
+impl SamplePattern {
+    fn new(samples: usize) -> Self {
+        let mut x_deltas = vec![0.0; samples];
+        let mut y_deltas = vec![0.0; samples];
+
+        spread_samples((0.0, 1.0), (1.0, 0.0), &mut x_deltas, &mut y_deltas);
+        // Lens samples are strata of the same square, walked in reverse so they don't line up
+        // with pixel samples.
+        let lens_x_deltas = x_deltas.iter().rev().copied().collect();
+        let lens_y_deltas = y_deltas.iter().rev().copied().collect();
+
+        Self {
+            x_deltas,
+            y_deltas,
+            lens_x_deltas,
+            lens_y_deltas,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.x_deltas.len()
+    }
+}
+
+/// What stays the same for every chunk of a frame.
+struct FrameContext<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    frame: usize,
+    previous_frame: Option<&'a PreviousFrame>,
+    // Of the view being rendered, half the frame's width side by side.
+    width: Real,
+    height: Real,
 }
 
 impl Renderer {
+    /// Panics unless there are samples to take and a bounce to trace at least.
     pub fn new(dimensions: (u16, u16), samples_per_pixel: usize, max_depth: usize) -> Self {
+        assert!(samples_per_pixel > 0, "no samples per pixel");
+        assert!(max_depth > 0, "no bounces to trace");
         let (width, height) = dimensions;
-        let lanes_per_line = width as usize / LANES;
-        let mut x_deltas = vec![ZEROS; samples_per_pixel];
-        let mut y_deltas = vec![ZEROS; samples_per_pixel];
-
-        spread_samples((0.0, 1.0), (1.0, 0.0), &mut x_deltas, &mut y_deltas);
+        let simd_level = SimdLevel::detect();
 
         Self {
             width: width as f32,
             height: height as f32,
-            lanes_per_line,
-            samples_per_pixel,
             max_depth,
-            x_deltas,
-            y_deltas,
+            shutter: 0.0,
+            mode: RenderMode::Shaded,
+            collect_stats: false,
+            stats: RayStats::default(),
+            simd_level,
+            lanes: simd_level.lanes(),
+            samples: SamplePattern::new(samples_per_pixel),
+            edge_samples: None,
+            interlacing: Interlacing::Off,
+            stereo: Stereo::Off,
+            previous_frames: Vec::new(),
+            post_passes: Vec::new(),
+            exposure: 0.0,
+            tone_mapping: ToneMapping::Aces,
+            palette: Palette::Rgb,
+            hdr_pixels: Vec::new(),
+            views: Vec::new(),
+        }
+    }
+
+    /// Fraction of the last frame the virtual shutter stays open, `0.0` disables motion blur.
+    pub fn set_shutter(&mut self, shutter: f32) {
+        self.shutter = shutter.clamp(0.0, 1.0);
+    }
+
+    pub fn mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: RenderMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_collect_stats(&mut self, collect_stats: bool) {
+        self.collect_stats = collect_stats;
+    }
+
+    /// Counters collected since the previous call, only the traced rays unless
+    /// `set_collect_stats` is on.
+    pub fn take_stats(&self) -> TraceCounters {
+        self.stats.take()
+    }
+
+    pub fn set_samples_per_pixel(&mut self, samples_per_pixel: usize) {
+        assert!(samples_per_pixel > 0, "no samples per pixel");
+        self.samples = SamplePattern::new(samples_per_pixel);
+    }
+
+    pub fn edge_samples(&self) -> Option<usize> {
+        self.edge_samples.as_ref().map(SamplePattern::len)
+    }
+
+    /// Adaptive sampling: after the regular samples, pixels on high contrast edges are traced
+    /// again with `edge_samples` samples each, which can't be 0. `None` turns it off.
+    pub fn set_edge_samples(&mut self, edge_samples: Option<usize>) {
+        assert!(edge_samples != Some(0), "no edge samples");
+        self.edge_samples = edge_samples.map(SamplePattern::new);
+    }
+
+    pub fn interlacing(&self) -> Interlacing {
+        self.interlacing
+    }
+
+    pub fn set_interlacing(&mut self, interlacing: Interlacing) {
+        self.interlacing = interlacing;
+        self.previous_frames.clear();
+    }
+
+    pub fn stereo(&self) -> Stereo {
+        self.stereo
+    }
+
+    pub fn set_stereo(&mut self, stereo: Stereo) {
+        self.stereo = stereo;
+        self.previous_frames.clear();
+    }
+
+    pub fn post_passes(&self) -> &[PostPass] {
+        &self.post_passes
+    }
+
+    /// Passes run in order over the linear light of shaded frames, before quantization.
+    pub fn set_post_passes(&mut self, post_passes: Vec<PostPass>) {
+        self.post_passes = post_passes;
+    }
+
+    pub fn exposure(&self) -> Real {
+        self.exposure
+    }
+
+    /// Scales the light of shaded frames by 2 to the power of `exposure` before tone mapping.
+    pub fn set_exposure(&mut self, exposure: Real) {
+        self.exposure = exposure;
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    /// Colors shaded frames are quantized to once encoded.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_dimensions(&mut self, dimensions: (u16, u16)) {
+        self.width = dimensions.0 as f32;
+        self.height = dimensions.1 as f32;
+        self.previous_frames.clear();
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.lanes
+    }
+
+    /// Overrides the lane count picked for the CPU, one of `SUPPORTED_LANES`. Lanes wider than
+    /// the CPU's registers still work, just split over several instructions.
+    pub fn set_lanes(&mut self, lanes: usize) {
+        if SUPPORTED_LANES.contains(&lanes) {
+            self.lanes = lanes;
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        frame: usize,
+        coef: f32,
+        buffer: &mut [[u8; 4]],
+    ) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut previous_frames = std::mem::take(&mut self.previous_frames);
+        let mut views = std::mem::take(&mut self.views);
+        let stereo = self.stereo.fitting(width);
+        let view_sizes = stereo.views(width);
+        for (i, &(eye_offset, view_width)) in view_sizes.iter().enumerate() {
+            let mut view_camera = camera.clone();
+            view_camera.set_aspect_ratio(view_width as Real / self.height);
+            let view_camera = view_camera.eye(eye_offset);
+            if views.len() <= i {
+                views.push(Vec::new());
+            }
+            views[i].resize(view_width * height, Color::new(0.0, 0.0, 0.0));
+            let context = FrameContext {
+                scene,
+                camera: &view_camera,
+                frame,
+                previous_frame: previous_frames.get(i),
+                width: view_width as Real,
+                height: self.height,
+            };
+            match self.lanes {
+                16 => self.render_lanes::<16>(&context, &mut views[i]),
+                8 => self.render_lanes::<8>(&context, &mut views[i]),
+                _ => self.render_lanes::<4>(&context, &mut views[i]),
+            }
+
+            if self.interlacing != Interlacing::Off {
+                // Reuses the allocation of the frame before.
+                let mut pixels = previous_frames
+                    .get_mut(i)
+                    .map(|frame| std::mem::take(&mut frame.pixels))
+                    .unwrap_or_default();
+                pixels.clear();
+                pixels.extend_from_slice(&views[i]);
+                self.previous_frames.push(PreviousFrame {
+                    camera: view_camera,
+                    pixels,
+                });
+            }
+        }
+
+        // Debug modes output values, not light, so they skip post-processing and tone mapping.
+        // Every view gets passes of its own, side by side ones with vignettes and aberration
+        // centered on them rather than on the seam.
+        let tone_mapping = if self.mode == RenderMode::Shaded {
+            for (view, &(_, view_width)) in views.iter_mut().zip(&view_sizes) {
+                for pass in &self.post_passes {
+                    pass.apply(view, view_width, frame);
+                }
+            }
+            Some(self.tone_mapping)
+        } else {
+            None
+        };
+
+        let mut hdr_pixels = std::mem::take(&mut self.hdr_pixels);
+        hdr_pixels.resize(buffer.len(), Color::new(0.0, 0.0, 0.0));
+        match stereo {
+            Stereo::Off => std::mem::swap(&mut hdr_pixels, &mut views[0]),
+            // The left eye only sees red through red/cyan glasses, the right one the rest.
+            Stereo::Anaglyph => {
+                for ((pixel, left), right) in hdr_pixels.iter_mut().zip(&views[0]).zip(&views[1]) {
+                    *pixel = Color::new(left.x(), right.y(), right.z());
+                }
+            }
+            Stereo::SideBySide => {
+                let left_width = views[0].len() / height;
+                for (y, line) in hdr_pixels.chunks_mut(width).enumerate() {
+                    let (left, right) = line.split_at_mut(left_width);
+                    left.copy_from_slice(&views[0][y * left.len()..(y + 1) * left.len()]);
+                    right.copy_from_slice(&views[1][y * right.len()..(y + 1) * right.len()]);
+                }
+            }
+        }
+        self.views = views;
+
+        encode(
+            &hdr_pixels,
+            self.exposure.exp2(),
+            tone_mapping,
+            coef,
+            buffer,
+        );
+        if tone_mapping.is_some() {
+            self.palette.apply(buffer, self.width as usize);
         }
+        self.hdr_pixels = hdr_pixels;
     }
 
-    pub fn render(&self, scene: &Scene, camera: &Camera, coef: f32, buffer: &mut [[u8; 4]]) {
+    fn render_lanes<const N: usize>(&self, context: &FrameContext, buffer: &mut [Color])
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let tiles = split_into_tiles(buffer, context.width as usize);
+        // Rayon steals whole tiles between threads, so expensive ones don't hold up the rest.
         #[cfg(not(target_arch = "wasm32"))]
-        let chunks = buffer.par_chunks_exact_mut(LANES);
+        let tiles = tiles.into_par_iter();
         #[cfg(target_arch = "wasm32")]
-        let chunks = buffer.chunks_exact_mut(LANES);
-        chunks.enumerate().for_each(|(n, slice)| {
-            let y = n / self.lanes_per_line;
-            let x = n % self.lanes_per_line;
-            let mut pixels_colors = ZERO_POINTS;
-            for sample in 0..self.samples_per_pixel {
-                let mut x_offsets = ZEROS;
-                let mut y_offsets = Reals::splat(y as f32);
-                for i in 0..LANES {
-                    x_offsets[i] = (x * LANES + i) as f32;
+        let tiles = tiles.into_iter();
+        tiles.for_each(|tile| self.dispatch_tile::<N>(context, tile));
+
+        if self.interlacing != Interlacing::Off {
+            self.fill_interlaced::<N>(context, buffer);
+        }
+
+        if let Some(edge_samples) = &self.edge_samples {
+            self.refine_edges::<N>(context, edge_samples, buffer);
+        }
+    }
+
+    /// Goes through the entry point compiled for the widest instruction set the CPU has, as
+    /// long as `N` lanes fill its registers.
+    fn dispatch_tile<const N: usize>(&self, context: &FrameContext, tile: Tile)
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        match self.simd_level.for_lanes(N) {
+            // Safe as `SimdLevel::detect` checked the CPU supports these features.
+            SimdLevel::Avx512 => return unsafe { render_tile_avx512(self, context, tile) },
+            SimdLevel::Avx2 => return unsafe { render_tile_avx2(self, context, tile) },
+            SimdLevel::Base => {}
+        }
+        self.render_tile::<N>(context, tile)
+    }
+
+    #[inline(always)]
+    fn render_tile<const N: usize>(&self, context: &FrameContext, tile: Tile)
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let (packet_width, packet_height) = packet_shape(N);
+        let (grid_width, grid_height) = self.interlacing.tile_grid();
+        let mut counters = TraceCounters::default();
+        for packet_row in (0..grid_height).step_by(packet_height) {
+            for packet_column in (0..grid_width).step_by(packet_width) {
+                let mut xs = Integers::splat(tile.x as Integer);
+                let mut ys = Integers::splat(tile.y as Integer);
+                for i in 0..N {
+                    let (x, y) = self.interlacing.tile_offset(
+                        packet_column + i % packet_width,
+                        packet_row + i / packet_width,
+                        context.frame,
+                    );
+                    xs[i] += x as Integer;
+                    ys[i] += y as Integer;
+                }
+
+                let active_lanes = xs.simd_lt(Integers::splat(context.width as Integer))
+                    & ys.simd_lt(Integers::splat(context.height as Integer));
+                // Tiles along the right and bottom edges stick out of the frame.
+                if !active_lanes.any() {
+                    continue;
+                }
+                let pixels_colors =
+                    self.render_packet(context, &self.samples, xs, ys, active_lanes, &mut counters);
+                for i in 0..N {
+                    let row = ys[i] as usize - tile.y;
+                    let column = xs[i] as usize - tile.x;
+                    // Rows past the bottom edge are empty, past the right one they are short.
+                    if let Some(pixel) = tile.rows[row].get_mut(column) {
+                        *pixel = pixels_colors.lane(i);
+                    }
                 }
+            }
+        }
+        self.stats.add(&counters);
+    }
 
-                x_offsets += &self.x_deltas[sample];
-                y_offsets += &self.y_deltas[sample];
-                x_offsets /= Reals::splat(self.width);
-                y_offsets /= Reals::splat(self.height);
+    /// Fills the pixels interlacing skipped this frame with what the previous frame showed at
+    /// the same surface points.
+    fn fill_interlaced<const N: usize>(&self, context: &FrameContext, buffer: &mut [Color])
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let width = context.width as usize;
+        let traced = buffer.to_vec();
+        #[cfg(not(target_arch = "wasm32"))]
+        let lines = buffer.par_chunks_mut(width);
+        #[cfg(target_arch = "wasm32")]
+        let lines = buffer.chunks_mut(width);
+        lines
+            .enumerate()
+            .for_each(|(y, line)| self.fill_line::<N>(context, &traced, y, line));
+    }
 
-                let rays = camera.pixel_rays(x_offsets, y_offsets);
-                pixels_colors += trace_rays(scene, rays, self.max_depth);
+    fn fill_line<const N: usize>(
+        &self,
+        context: &FrameContext,
+        traced: &[Color],
+        y: usize,
+        line: &mut [Color],
+    ) where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let missing: Vec<_> = (0..line.len())
+            .filter(|&x| !self.interlacing.traces(x, y, context.frame))
+            .collect();
+        let mut lane_ids = Integers::splat(0);
+        for i in 0..N {
+            lane_ids[i] = i as Integer;
+        }
+
+        for xs in missing.chunks(N) {
+            // Only where pixel centers land matters, not the look of the surfaces.
+            let mut x_offsets = zeros();
+            for (i, &x) in xs.iter().enumerate() {
+                x_offsets[i] = (x as Real + 0.5) / context.width;
+            }
+            let y_offsets = Reals::splat((y as Real + 0.5) / context.height);
+            let rays = context
+                .camera
+                .pixel_rays(x_offsets, y_offsets, Points::ZERO);
+            let active_lanes = lane_ids.simd_lt(Integers::splat(xs.len() as Integer));
+            let positions = previous_positions(context.scene, rays, active_lanes);
+            for (i, &x) in xs.iter().enumerate() {
+                line[x] = self.reproject(context, traced, x, y, positions.lane(i));
             }
-            pixels_colors /= Reals::splat(self.samples_per_pixel as f32);
-            pixels_colors = pixels_colors.sqrt().normalize();
-            for (i, pixel) in slice.iter_mut().enumerate().take(LANES) {
-                pixel[0] = (pixels_colors.xs[i] * 255.0 * coef) as u8;
-                pixel[1] = (pixels_colors.ys[i] * 255.0 * coef) as u8;
-                pixel[2] = (pixels_colors.zs[i] * 255.0 * coef) as u8;
+        }
+    }
+
+    /// Color of the pixel at `x` and `y`, at which `position` showed a frame earlier. The
+    /// traced neighbours bound it, so what has moved or got uncovered since doesn't smear.
+    fn reproject(
+        &self,
+        context: &FrameContext,
+        traced: &[Color],
+        x: usize,
+        y: usize,
+        position: Point,
+    ) -> Color {
+        let width = context.width as usize;
+        let height = context.height as usize;
+        let mut low = Color::new(Real::MAX, Real::MAX, Real::MAX);
+        let mut high = Color::new(0.0, 0.0, 0.0);
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let mut count = 0;
+        for neighbour_y in y.saturating_sub(1)..(y + 2).min(height) {
+            for neighbour_x in x.saturating_sub(1)..(x + 2).min(width) {
+                if !self
+                    .interlacing
+                    .traces(neighbour_x, neighbour_y, context.frame)
+                {
+                    continue;
+                }
+                let neighbour = traced[neighbour_y * width + neighbour_x];
+                low = low.min(neighbour);
+                high = high.max(neighbour);
+                sum = sum + neighbour;
+                count += 1;
             }
+        }
+
+        let previous = context.previous_frame.and_then(|previous_frame| {
+            let (x_offset, y_offset) = previous_frame.camera.project(position)?;
+            let previous_x = (x_offset * context.width).floor();
+            let previous_y = (y_offset * context.height).floor();
+            let inside = (0.0..context.width).contains(&previous_x)
+                && (0.0..context.height).contains(&previous_y);
+            inside.then(|| previous_frame.pixels[previous_y as usize * width + previous_x as usize])
+        });
+        match previous {
+            Some(color) => color.max(low).min(high),
+            // Nothing to reproject, on the first frame or from off screen.
+            None => sum / count.max(1) as Real,
+        }
+    }
+
+    /// Traces the pixels that differ a lot from one of their neighbours again, with samples
+    /// of `pattern`, smoothing edges the regular samples left jagged.
+    fn refine_edges<const N: usize>(
+        &self,
+        context: &FrameContext,
+        pattern: &SamplePattern,
+        buffer: &mut [Color],
+    ) where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let edges = find_edges(buffer, context.width as usize);
+        // Edge pixels are scattered all over the frame, so they are traced in batches of a
+        // tile's worth of pixels and written back once all are done.
+        let batch_size = TILE_SIZE * TILE_SIZE;
+        let mut colors = vec![Color::new(0.0, 0.0, 0.0); edges.len()];
+        #[cfg(not(target_arch = "wasm32"))]
+        let batches = edges
+            .par_chunks(batch_size)
+            .zip(colors.par_chunks_mut(batch_size));
+        #[cfg(target_arch = "wasm32")]
+        let batches = edges.chunks(batch_size).zip(colors.chunks_mut(batch_size));
+        batches.for_each(|(pixels, colors)| {
+            self.dispatch_edges::<N>(context, pattern, pixels, colors)
         });
+
+        for (&pixel, &color) in edges.iter().zip(&colors) {
+            buffer[pixel] = color;
+        }
+    }
+
+    fn dispatch_edges<const N: usize>(
+        &self,
+        context: &FrameContext,
+        pattern: &SamplePattern,
+        pixels: &[usize],
+        colors: &mut [Color],
+    ) where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        match self.simd_level.for_lanes(N) {
+            // Safe as `SimdLevel::detect` checked the CPU supports these features.
+            SimdLevel::Avx512 => {
+                return unsafe { render_edges_avx512(self, context, pattern, pixels, colors) }
+            }
+            SimdLevel::Avx2 => {
+                return unsafe { render_edges_avx2(self, context, pattern, pixels, colors) }
+            }
+            SimdLevel::Base => {}
+        }
+        self.render_edges::<N>(context, pattern, pixels, colors)
+    }
+
+    /// Renders the pixels at buffer indices `pixels` into `colors`, packing them `N` at a time.
+    #[inline(always)]
+    fn render_edges<const N: usize>(
+        &self,
+        context: &FrameContext,
+        pattern: &SamplePattern,
+        pixels: &[usize],
+        colors: &mut [Color],
+    ) where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let width = context.width as usize;
+        let mut counters = TraceCounters::default();
+        let mut lane_ids = Integers::splat(0);
+        for i in 0..N {
+            lane_ids[i] = i as Integer;
+        }
+
+        for (pixels, colors) in pixels.chunks(N).zip(colors.chunks_mut(N)) {
+            let mut xs = Integers::splat(0);
+            let mut ys = Integers::splat(0);
+            for (i, &pixel) in pixels.iter().enumerate() {
+                xs[i] = (pixel % width) as Integer;
+                ys[i] = (pixel / width) as Integer;
+            }
+            let active_lanes = lane_ids.simd_lt(Integers::splat(pixels.len() as Integer));
+            let pixels_colors =
+                self.render_packet(context, pattern, xs, ys, active_lanes, &mut counters);
+            for (i, color) in colors.iter_mut().enumerate() {
+                *color = pixels_colors.lane(i);
+            }
+        }
+
+        self.stats.add(&counters);
+    }
+
+    /// Linear light of the pixels at `xs` and `ys`, or debug values.
+    #[inline(always)]
+    fn render_packet<const N: usize>(
+        &self,
+        context: &FrameContext,
+        pattern: &SamplePattern,
+        xs: Integers<N>,
+        ys: Integers<N>,
+        active_lanes: Mask<N>,
+        counters: &mut TraceCounters,
+    ) -> Colors<N>
+    where
+        LaneCount<N>: SupportedLaneCount,
+    {
+        let mut pixels_colors = Points::ZERO;
+        let pixel_ids = ys * Integers::splat(context.width as Integer) + xs;
+        // The stream past the last sample shifts lens strata per pixel, turning lens banding
+        // into noise.
+        let mut pixel_random = Random::new(pixel_ids, pattern.len(), context.frame);
+        let lens_x_shifts = pixel_random.next_reals();
+        let lens_y_shifts = pixel_random.next_reals();
+        for sample in 0..pattern.len() {
+            let mut x_offsets = zeros();
+            let mut y_offsets = zeros();
+            for i in 0..N {
+                x_offsets[i] = xs[i] as Real;
+                y_offsets[i] = ys[i] as Real;
+            }
+
+            x_offsets += Reals::splat(pattern.x_deltas[sample]);
+            y_offsets += Reals::splat(pattern.y_deltas[sample]);
+            x_offsets /= Reals::splat(context.width);
+            y_offsets /= Reals::splat(context.height);
+
+            let mut random = Random::new(pixel_ids, sample, context.frame);
+            let lens_xs = Reals::splat(pattern.lens_x_deltas[sample]) + lens_x_shifts;
+            let lens_ys = Reals::splat(pattern.lens_y_deltas[sample]) + lens_y_shifts;
+            let lens_offsets = square_to_disk(lens_xs - lens_xs.floor(), lens_ys - lens_ys.floor());
+
+            // Samples split the shutter interval into strata, lanes jitter within them.
+            let times = (Reals::splat(sample as f32) + random.next_reals())
+                / Reals::splat(pattern.len() as f32);
+            let time_offsets = (times - Reals::splat(1.0)) * Reals::splat(self.shutter);
+
+            let rays = context
+                .camera
+                .pixel_rays(x_offsets, y_offsets, lens_offsets);
+            pixels_colors += trace_rays(
+                context.scene,
+                rays,
+                active_lanes,
+                time_offsets,
+                TraceOptions {
+                    max_depth: self.max_depth,
+                    mode: self.mode,
+                    collect_stats: self.collect_stats,
+                },
+                &mut random,
+                counters,
+            );
+        }
+        pixels_colors /= Reals::splat(pattern.len() as f32);
+        pixels_colors
+    }
+}
+
+/// Square block of pixels rendered as a whole by one thread, `rows` borrow its part of every
+/// line of the frame.
+struct Tile<'a> {
+    x: usize,
+    y: usize,
+    rows: [&'a mut [Color]; TILE_SIZE],
+}
+
+fn split_into_tiles(buffer: &mut [Color], width: usize) -> Vec<Tile<'_>> {
+    let mut tiles = Vec::new();
+    for (band, band_pixels) in buffer.chunks_mut(width * TILE_SIZE).enumerate() {
+        let mut lines: Vec<_> = band_pixels
+            .chunks_mut(width)
+            .map(|line| line.chunks_mut(TILE_SIZE))
+            .collect();
+        for x in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                x,
+                y: band * TILE_SIZE,
+                rows: std::array::from_fn(|row| {
+                    lines
+                        .get_mut(row)
+                        .and_then(Iterator::next)
+                        .unwrap_or_default()
+                }),
+            });
+        }
+    }
+    tiles
+}
+
+/// Pixels traced together, as square as the lane count allows for coherent rays.
+const fn packet_shape(lanes: usize) -> (usize, usize) {
+    match lanes {
+        16 => (4, 4),
+        8 => (4, 2),
+        _ => (2, 2),
     }
 }
 
+/// Indices of the pixels with a channel more than `EDGE_THRESHOLD` off the one of a
+/// neighbour, in order.
+fn find_edges(buffer: &[Color], width: usize) -> Vec<usize> {
+    let differ = |a: Color, b: Color| {
+        let one = Color::new(1.0, 1.0, 1.0);
+        let (a, b) = (a.min(one), b.min(one));
+        [(a.x(), b.x()), (a.y(), b.y()), (a.z(), b.z())]
+            .iter()
+            .any(|&(a, b)| (a.sqrt() - b.sqrt()).abs() > EDGE_THRESHOLD)
+    };
+    let is_edge = |&index: &usize| {
+        let pixel = buffer[index];
+        let x = index % width;
+        (x > 0 && differ(pixel, buffer[index - 1]))
+            || (x + 1 < width && differ(pixel, buffer[index + 1]))
+            || (index >= width && differ(pixel, buffer[index - width]))
+            || (index + width < buffer.len() && differ(pixel, buffer[index + width]))
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    let indices = (0..buffer.len()).into_par_iter();
+    #[cfg(target_arch = "wasm32")]
+    let indices = 0..buffer.len();
+    indices.filter(is_edge).collect()
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn render_tile_avx2(renderer: &Renderer, context: &FrameContext, tile: Tile) {
+    renderer.render_tile::<8>(context, tile)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f")]
+unsafe fn render_tile_avx512(renderer: &Renderer, context: &FrameContext, tile: Tile) {
+    renderer.render_tile::<16>(context, tile)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn render_edges_avx2(
+    renderer: &Renderer,
+    context: &FrameContext,
+    pattern: &SamplePattern,
+    pixels: &[usize],
+    colors: &mut [Color],
+) {
+    renderer.render_edges::<8>(context, pattern, pixels, colors)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512f")]
+unsafe fn render_edges_avx512(
+    renderer: &Renderer,
+    context: &FrameContext,
+    pattern: &SamplePattern,
+    pixels: &[usize],
+    colors: &mut [Color],
+) {
+    renderer.render_edges::<16>(context, pattern, pixels, colors)
+}
+
 fn spread_samples(
     top_left: (f32, f32),
     bottom_right: (f32, f32),
-    x_deltas: &mut [Reals],
-    y_deltas: &mut [Reals],
+    x_deltas: &mut [Real],
+    y_deltas: &mut [Real],
 ) {
     let samples_left = x_deltas.len();
     if samples_left == 1 {
-        x_deltas[0] = Reals::splat((top_left.0 + bottom_right.0) / 2.0);
-        y_deltas[0] = Reals::splat((top_left.1 + bottom_right.1) / 2.0);
+        x_deltas[0] = (top_left.0 + bottom_right.0) / 2.0;
+        y_deltas[0] = (top_left.1 + bottom_right.1) / 2.0;
     } else {
         let samples_count_a = samples_left / 2;
         let (x_deltas_a, x_deltas_b) = x_deltas.split_at_mut(samples_count_a);
@@ -118,3 +894,30 @@ fn spread_samples(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let (width, height) = (TILE_SIZE * 2 + 5, TILE_SIZE + 3);
+        let mut buffer = vec![Color::new(0.0, 0.0, 0.0); width * height];
+        for tile in split_into_tiles(&mut buffer, width) {
+            for (row, line) in tile.rows.into_iter().enumerate() {
+                assert!(line.len() <= TILE_SIZE);
+                for (column, pixel) in line.iter_mut().enumerate() {
+                    let (x, y) = (tile.x + column, tile.y + row);
+                    *pixel = Color::new(x as Real, y as Real, pixel.z() + 1.0);
+                }
+            }
+        }
+        for (i, pixel) in buffer.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            assert_eq!(
+                (pixel.x(), pixel.y(), pixel.z()),
+                (x as Real, y as Real, 1.0)
+            );
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Quantization of the encoded frame to the colors of the ZX Spectrum, the machine the original
+//! Room Ten ran on.
+
+use crate::math::Real;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+
+/// Width and height of the Spectrum's screen, in pixels.
+pub const SPECTRUM_RESOLUTION: (u16, u16) = (256, 192);
+// Attribute cells are this many pixels wide and high.
+const CELL_SIZE: usize = 8;
+// Channels of the normal and bright halves of the palette, black being in both.
+const NORMAL_LEVEL: u8 = 0xd7;
+const BRIGHT_LEVEL: u8 = 0xff;
+
+named_enum! {
+    /// Colors the frame is shown with.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum Palette {
+        /// Every color the encoding can represent.
+        Rgb => "rgb",
+        /// The 15 colors of the Spectrum, dithered.
+        Spectrum => "spectrum",
+        /// The Spectrum's colors with its attribute clash: only two of them, of the same
+        /// brightness, in every 8x8 cell.
+        SpectrumAttributes => "spectrum-attributes",
+    }
+}
+
+impl Palette {
+    /// Replaces every pixel of `buffer` by a color of the palette, leaving alpha alone.
+    pub fn apply(&self, buffer: &mut [[u8; 4]], width: usize) {
+        let attributes = match self {
+            Palette::Rgb => return,
+            Palette::Spectrum => false,
+            Palette::SpectrumAttributes => true,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let bands = buffer.par_chunks_mut(width * CELL_SIZE);
+        #[cfg(target_arch = "wasm32")]
+        let bands = buffer.chunks_mut(width * CELL_SIZE);
+        bands.enumerate().for_each(|(band, pixels)| {
+            let y = band * CELL_SIZE;
+            if attributes {
+                for x in (0..width).step_by(CELL_SIZE) {
+                    quantize_cell(pixels, width, x, y);
+                }
+            } else {
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    let color = SpectrumColor::nearest(rgb(pixel), bayer(i % width, y + i / width));
+                    set_rgb(pixel, color.rgb());
+                }
+            }
+        });
+    }
+}
+
+/// One of the 8 colors of the Spectrum, its bits being blue, red and green, in either
+/// brightness.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SpectrumColor {
+    bits: usize,
+    bright: bool,
+}
+
+impl SpectrumColor {
+    const BLACK: SpectrumColor = SpectrumColor {
+        bits: 0,
+        bright: false,
+    };
+
+    fn rgb(&self) -> [Real; 3] {
+        let level = if self.bright {
+            BRIGHT_LEVEL
+        } else {
+            NORMAL_LEVEL
+        } as Real;
+        let channel = |bit: usize| if self.bits & bit != 0 { level } else { 0.0 };
+        [channel(2), channel(4), channel(1)]
+    }
+
+    /// Closest color to `rgb` once shifted by the dithering `threshold`, between 0 and 1.
+    fn nearest(rgb: [Real; 3], threshold: Real) -> SpectrumColor {
+        let shift = (threshold - 0.5) * BRIGHT_LEVEL as Real;
+        let rgb = rgb.map(|channel| channel + shift);
+        let mut nearest = (SpectrumColor::BLACK, Real::INFINITY);
+        for bits in 0..8 {
+            for bright in [false, true] {
+                let color = SpectrumColor { bits, bright };
+                let distance = distance(rgb, color.rgb());
+                if distance < nearest.1 {
+                    nearest = (color, distance);
+                }
+            }
+        }
+        nearest.0
+    }
+}
+
+/// Picks the two colors the pixels of the 8x8 cell at `x` and `y` of the band are closest to,
+/// and dithers every pixel between them.
+fn quantize_cell(band: &mut [[u8; 4]], width: usize, x: usize, y: usize) {
+    let cell_width = CELL_SIZE.min(width - x);
+    let cell_height = band.len() / width;
+    let cell = |row: usize| row * width + x..row * width + x + cell_width;
+
+    // Votes for every color and brightness by the pixels dithered on their own.
+    let mut color_votes = [0; 8];
+    let mut bright_votes = 0;
+    for row in 0..cell_height {
+        for (column, pixel) in band[cell(row)].iter().enumerate() {
+            let color = SpectrumColor::nearest(rgb(pixel), bayer(x + column, y + row));
+            color_votes[color.bits] += 1;
+            if color.bits != 0 {
+                bright_votes += if color.bright { 1 } else { -1 };
+            }
+        }
+    }
+    let mut ranked: [usize; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+    ranked.sort_by_key(|&bits| std::cmp::Reverse(color_votes[bits]));
+    let bright = bright_votes > 0;
+    let ink = SpectrumColor {
+        bits: ranked[0],
+        bright,
+    };
+    let paper = SpectrumColor {
+        bits: if color_votes[ranked[1]] > 0 {
+            ranked[1]
+        } else {
+            ranked[0]
+        },
+        bright,
+    };
+
+    let (ink_rgb, paper_rgb) = (ink.rgb(), paper.rgb());
+    let span = distance(paper_rgb, ink_rgb);
+    for row in 0..cell_height {
+        for (column, pixel) in band[cell(row)].iter_mut().enumerate() {
+            // How far along from ink to paper the pixel is, `span` being squared.
+            let along = if span > 0.0 {
+                let rgb = rgb(pixel);
+                (0..3)
+                    .map(|i| (rgb[i] - ink_rgb[i]) * (paper_rgb[i] - ink_rgb[i]))
+                    .sum::<Real>()
+                    / span
+            } else {
+                0.0
+            };
+            let color = if along > bayer(x + column, y + row) {
+                paper_rgb
+            } else {
+                ink_rgb
+            };
+            set_rgb(pixel, color);
+        }
+    }
+}
+
+/// Threshold of the 8x8 ordered dithering matrix at `x` and `y`, between 0 and 1.
+fn bayer(x: usize, y: usize) -> Real {
+    let (x, y) = (x % CELL_SIZE, y % CELL_SIZE);
+    let mut index = 0;
+    // Interleaves the bits of `x ^ y` and `y`, the lowest ones the most significant.
+    for bit in 0..3 {
+        index = index << 2 | ((x ^ y) >> bit & 1) << 1 | (y >> bit & 1);
+    }
+    (index as Real + 0.5) / (CELL_SIZE * CELL_SIZE) as Real
+}
+
+/// Squared distance between two colors.
+fn distance(a: [Real; 3], b: [Real; 3]) -> Real {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+fn rgb(pixel: &[u8; 4]) -> [Real; 3] {
+    [pixel[0] as Real, pixel[1] as Real, pixel[2] as Real]
+}
+
+fn set_rgb(pixel: &mut [u8; 4], rgb: [Real; 3]) {
+    for (output, channel) in pixel.iter_mut().zip(rgb) {
+        *output = channel as u8;
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Deeper bounces are counted together with the last tracked one.
+pub const TRACKED_DEPTH: usize = 8;
+pub const SPHERE_EXIT_STAGES: usize = 3;
+
+/// Counters of a single `trace_rays` call, merged into `RayStats` afterwards.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TraceCounters {
+    pub rays_per_depth: [u64; TRACKED_DEPTH],
+    pub simd_ops: u64,
+    pub active_lanes: u64,
+    pub sphere_tests: u64,
+    pub sphere_early_exits: [u64; SPHERE_EXIT_STAGES],
+}
+
+impl TraceCounters {
+    pub fn count_rays(&mut self, depth: usize, rays: u64) {
+        self.rays_per_depth[depth.min(TRACKED_DEPTH - 1)] += rays;
+    }
+
+    pub fn merge(&mut self, other: &TraceCounters) {
+        for (count, other_count) in self.rays_per_depth.iter_mut().zip(other.rays_per_depth) {
+            *count += other_count;
+        }
+        self.simd_ops += other.simd_ops;
+        self.active_lanes += other.active_lanes;
+        self.sphere_tests += other.sphere_tests;
+        for (count, other_count) in self
+            .sphere_early_exits
+            .iter_mut()
+            .zip(other.sphere_early_exits)
+        {
+            *count += other_count;
+        }
+    }
+}
+
+/// Totals since the last `take`, updated from all the rendering threads without locks.
+#[derive(Default)]
+pub struct RayStats {
+    rays_per_depth: [AtomicU64; TRACKED_DEPTH],
+    simd_ops: AtomicU64,
+    active_lanes: AtomicU64,
+    sphere_tests: AtomicU64,
+    sphere_early_exits: [AtomicU64; SPHERE_EXIT_STAGES],
+}
+
+impl RayStats {
+    pub fn add(&self, counters: &TraceCounters) {
+        for (total, &count) in self.rays_per_depth.iter().zip(&counters.rays_per_depth) {
+            total.fetch_add(count, Ordering::Relaxed);
+        }
+        self.simd_ops
+            .fetch_add(counters.simd_ops, Ordering::Relaxed);
+        self.active_lanes
+            .fetch_add(counters.active_lanes, Ordering::Relaxed);
+        self.sphere_tests
+            .fetch_add(counters.sphere_tests, Ordering::Relaxed);
+        for (total, &count) in self
+            .sphere_early_exits
+            .iter()
+            .zip(&counters.sphere_early_exits)
+        {
+            total.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the totals and starts counting from zero.
+    pub fn take(&self) -> TraceCounters {
+        let mut counters = TraceCounters::default();
+        for (count, total) in counters.rays_per_depth.iter_mut().zip(&self.rays_per_depth) {
+            *count = total.swap(0, Ordering::Relaxed);
+        }
+        counters.simd_ops = self.simd_ops.swap(0, Ordering::Relaxed);
+        counters.active_lanes = self.active_lanes.swap(0, Ordering::Relaxed);
+        counters.sphere_tests = self.sphere_tests.swap(0, Ordering::Relaxed);
+        for (count, total) in counters
+            .sphere_early_exits
+            .iter_mut()
+            .zip(&self.sphere_early_exits)
+        {
+            *count = total.swap(0, Ordering::Relaxed);
+        }
+        counters
+    }
+}
+
+/// Seconds spent on each part of a frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameTimes {
+    pub render: f64,
+    pub physics: f64,
+    pub upload: f64,
+}
+
+/// Everything measured during one frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameStats {
+    pub counters: TraceCounters,
+    pub times: FrameTimes,
+    pub lanes: usize,
+}
+
+impl FrameStats {
+    pub fn total_rays(&self) -> u64 {
+        self.counters.rays_per_depth.iter().sum()
+    }
+
+    /// Share of SIMD lanes doing useful work, masked out lanes are wasted.
+    pub fn lane_utilization(&self) -> f64 {
+        if self.counters.simd_ops == 0 {
+            return 0.0;
+        }
+        self.counters.active_lanes as f64 / (self.counters.simd_ops * self.lanes as u64) as f64
+    }
+
+    /// Share of sphere tests that returned early at each stage.
+    pub fn sphere_early_exit_rates(&self) -> [f64; SPHERE_EXIT_STAGES] {
+        let tests = self.counters.sphere_tests.max(1) as f64;
+        self.counters
+            .sphere_early_exits
+            .map(|exits| exits as f64 / tests)
+    }
+
+    /// Rays actually traced per second of rendering.
+    pub fn rays_per_second(&self) -> f64 {
+        if self.times.render <= 0.0 {
+            return 0.0;
+        }
+        self.total_rays() as f64 / self.times.render
+    }
+
+    pub fn overlay_lines(&self) -> Vec<String> {
+        let exit_rates = self.sphere_early_exit_rates();
+        vec![
+            format!(
+                "render: {:.2}ms, physics: {:.2}ms, upload: {:.2}ms",
+                self.times.render * 1000.0,
+                self.times.physics * 1000.0,
+                self.times.upload * 1000.0
+            ),
+            format!(
+                "rays: {}, measured rps: {:.4}M",
+                self.total_rays(),
+                self.rays_per_second() / 1_000_000.0
+            ),
+            format!("rays per depth: {:?}", self.counters.rays_per_depth),
+            format!(
+                "lane utilization: {:.1}% of {} lanes",
+                self.lane_utilization() * 100.0,
+                self.lanes
+            ),
+            format!(
+                "sphere early exits: {:.1}% / {:.1}% / {:.1}%",
+                exit_rates[0] * 100.0,
+                exit_rates[1] * 100.0,
+                exit_rates[2] * 100.0
+            ),
+        ]
+    }
+
+    pub fn csv_header() -> String {
+        let mut header = String::from("frame,render_s,physics_s,upload_s,lanes");
+        for depth in 0..TRACKED_DEPTH {
+            header += &format!(",rays_depth_{}", depth);
+        }
+        header += ",simd_ops,active_lanes,sphere_tests";
+        for stage in 0..SPHERE_EXIT_STAGES {
+            header += &format!(",sphere_exits_{}", stage);
+        }
+        header
+    }
+
+    pub fn csv_row(&self, frame: usize) -> String {
+        let mut row = format!(
+            "{},{},{},{},{}",
+            frame, self.times.render, self.times.physics, self.times.upload, self.lanes
+        );
+        for count in self.counters.rays_per_depth {
+            row += &format!(",{}", count);
+        }
+        row += &format!(
+            ",{},{},{}",
+            self.counters.simd_ops, self.counters.active_lanes, self.counters.sphere_tests
+        );
+        for count in self.counters.sphere_early_exits {
+            row += &format!(",{}", count);
+        }
+        row
+    }
+}
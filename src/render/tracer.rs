@@ -1,63 +1,241 @@
 use crate::math::{
-    update_reals_if, Colors, Integer, Integers, Points, Rays, Reals, Vectors, ZEROS, ZERO_POINTS,
+    update_reals_if, zeros, Axis, Color, Colors, Integer, Integers, LaneCount, Mask, Points,
+    Random, Rays, Reals, SimdFloat, SimdInt, SimdPartialEq, SimdPartialOrd, StdFloat,
+    SupportedLaneCount, Vectors,
 };
-use crate::scene::{Obstacle, Plane, Scene, Sphere};
-use std::simd::{SimdFloat, SimdPartialEq, SimdPartialOrd, StdFloat};
+use crate::render::stats::TraceCounters;
+use crate::scene::{Obstacle, Plane, Scene, Sphere, OBSTACLES_COUNT};
 
-pub fn trace_rays(scene: &Scene, rays: Rays, max_depth: usize) -> Colors {
-    let mut projections = RaysProjections::new(scene, rays, max_depth);
+named_enum! {
+    /// What `trace_rays` outputs, everything but `Shaded` is there to debug the tracer.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub enum RenderMode {
+        Shaded => "shaded",
+        Normals => "normals",
+        Depth => "depth",
+        ObstacleIds => "ids",
+        Bounces => "bounces",
+    }
+}
+
+/// What stays the same for every `trace_rays` call of a frame.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions {
+    pub max_depth: usize,
+    pub mode: RenderMode,
+    /// Counts SIMD operations and sphere tests on top of the traced rays, at some cost.
+    pub collect_stats: bool,
+}
+
+// Primary hits this far away or further show up black in `RenderMode::Depth`.
+const DEBUG_MAX_DEPTH: f32 = 20.0;
+const OBSTACLE_IDS_COLORS: [Color; OBSTACLES_COUNT] = [
+    Color::new(1.0, 1.0, 0.0),
+    Color::new(0.0, 1.0, 1.0),
+    Color::new(1.0, 0.0, 1.0),
+    Color::new(1.0, 0.0, 0.0),
+    Color::new(0.0, 0.0, 1.0),
+    Color::new(0.0, 1.0, 0.0),
+    Color::new(1.0, 1.0, 1.0),
+    Color::new(1.0, 0.5, 0.0),
+    Color::new(0.5, 0.0, 1.0),
+];
+
+/// `time_offsets` are in frames relative to the current scene state, spheres are moved back
+/// along their last shifts by that much. Lanes off `active_lanes` stay black and don't count
+/// as traced rays, they fill packets sticking out of the frame.
+///
+/// Everything down from here is inlined into the caller, so the renderer's per instruction
+/// set entry points get the whole tracer compiled with their target features.
+#[inline(always)]
+pub fn trace_rays<const N: usize>(
+    scene: &Scene,
+    rays: Rays<N>,
+    active_lanes: Mask<N>,
+    time_offsets: Reals<N>,
+    options: TraceOptions,
+    random: &mut Random<N>,
+    counters: &mut TraceCounters,
+) -> Colors<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let mut projections = RaysProjections::new(
+        scene,
+        rays,
+        active_lanes,
+        time_offsets,
+        options.max_depth,
+        random,
+        Some(counters),
+    );
+    projections.collect_stats = options.collect_stats;
     loop {
-        projections.intersect_with_sphere(Sphere::Ball);
-        projections.intersect_with_sphere(Sphere::NearPaddle);
-        projections.intersect_with_sphere(Sphere::FarPaddle);
-        projections.intersect_with_aa_plane(Plane::Top);
-        projections.intersect_with_aa_plane(Plane::Bottom);
-        projections.intersect_with_aa_plane(Plane::Left);
-        projections.intersect_with_aa_plane(Plane::Right);
-        projections.intersect_with_aa_plane(Plane::Near);
-        projections.intersect_with_aa_plane(Plane::Far);
+        projections.intersect_with_all();
         if projections.reflect() {
             break;
         }
     }
-    projections.finish(Colors::splat(1.0, 1.0, 1.0))
+    match options.mode {
+        RenderMode::Shaded => projections.finish(Colors::splat(1.0, 1.0, 1.0)),
+        _ => projections.visualize(options.mode, options.max_depth),
+    }
+}
+
+/// Where the first surfaces the rays hit were a frame earlier: hit points on spheres move back
+/// along the last shift of their sphere, the room doesn't move.
+#[inline(always)]
+pub fn previous_positions<const N: usize>(
+    scene: &Scene,
+    rays: Rays<N>,
+    active_lanes: Mask<N>,
+) -> Points<N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    // Primary hits don't need any random directions.
+    let mut random = Random::new(Integers::splat(0), 0, 0);
+    let mut projections =
+        RaysProjections::new(scene, rays, active_lanes, zeros(), 1, &mut random, None);
+    projections.intersect_with_all();
+
+    let mut positions = projections.rays.origins + projections.rays.dirs * projections.min_toi;
+    for sphere in [Sphere::Ball, Sphere::NearPaddle, Sphere::FarPaddle] {
+        let mask = projections
+            .obstacle_ids
+            .simd_eq(Integers::splat(Obstacle::Sphere(sphere).id() as Integer));
+        positions.update_if(
+            mask,
+            positions - Vectors::from_single(scene.sphere_shift(sphere)),
+        );
+    }
+    positions
 }
 
-struct RaysProjections<'a> {
+struct RaysProjections<'a, const N: usize>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
     scene: &'a Scene,
-    rays: Rays,
-    min_toi: Reals,
-    obstacle_reflectances: Reals,
-    obstacle_colors: Colors,
-    obstacle_normals: Vectors,
-    offset_colors: Colors,
-    coef_colors: Colors,
+    random: &'a mut Random<N>,
+    counters: Option<&'a mut TraceCounters>,
+    collect_stats: bool,
+    rays: Rays<N>,
+    time_offsets: Reals<N>,
+    min_toi: Reals<N>,
+    obstacle_reflectances: Reals<N>,
+    obstacle_roughnesses: Reals<N>,
+    obstacle_colors: Colors<N>,
+    obstacle_normals: Vectors<N>,
+    obstacle_ids: Integers<N>,
+    offset_colors: Colors<N>,
+    coef_colors: Colors<N>,
     depth_left: usize,
+    depth: usize,
+    bounces: Integers<N>,
+    primary_tois: Reals<N>,
+    primary_normals: Vectors<N>,
+    primary_ids: Integers<N>,
 }
 
 const MIN_TOI: f32 = 0.001f32;
+// Walls keep this much of their color under the ball's markers.
+const WALL_MARKER_SHADE: f32 = 0.3;
+// The intercept ghost is an outline of the ball this thick, adding this much light.
+const GHOST_THICKNESS: f32 = 0.02;
+const GHOST_COLOR: Color = Color::new(0.6, 0.9, 1.0);
+// The depth ring is this wide along the walls and this many times brighter than its color, for
+// it to glow in the HDR buffer.
+const DEPTH_RING_WIDTH: f32 = 0.15;
+const DEPTH_RING_INTENSITY: f32 = 1.5;
 
-impl<'a> RaysProjections<'a> {
-    fn new(scene: &'a Scene, rays: Rays, max_depth: usize) -> RaysProjections<'a> {
+impl<'a, const N: usize> RaysProjections<'a, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    #[inline(always)]
+    fn new(
+        scene: &'a Scene,
+        rays: Rays<N>,
+        active_lanes: Mask<N>,
+        time_offsets: Reals<N>,
+        max_depth: usize,
+        random: &'a mut Random<N>,
+        counters: Option<&'a mut TraceCounters>,
+    ) -> RaysProjections<'a, N> {
+        let coefs = active_lanes.select(Reals::splat(1.0), zeros());
         RaysProjections {
             scene,
+            random,
+            counters,
+            collect_stats: false,
             rays,
+            time_offsets,
             min_toi: Reals::splat(std::f32::MAX),
             obstacle_reflectances: Reals::splat(std::f32::MAX),
-            obstacle_colors: ZERO_POINTS,
-            obstacle_normals: ZERO_POINTS,
-            offset_colors: ZERO_POINTS,
-            coef_colors: Colors::splat(1.0, 1.0, 1.0),
+            obstacle_roughnesses: zeros(),
+            obstacle_colors: Points::ZERO,
+            obstacle_normals: Points::ZERO,
+            obstacle_ids: Integers::splat(0),
+            offset_colors: Points::ZERO,
+            coef_colors: Colors {
+                xs: coefs,
+                ys: coefs,
+                zs: coefs,
+            },
             depth_left: max_depth,
+            depth: 0,
+            bounces: Integers::splat(0),
+            primary_tois: zeros(),
+            primary_normals: Points::ZERO,
+            primary_ids: Integers::splat(0),
         }
     }
 
+    #[inline(always)]
+    fn intersect_with_all(&mut self) {
+        self.intersect_with_sphere(Sphere::Ball);
+        self.intersect_with_sphere(Sphere::NearPaddle);
+        self.intersect_with_sphere(Sphere::FarPaddle);
+        self.intersect_with_aa_plane(Plane::Top);
+        self.intersect_with_aa_plane(Plane::Bottom);
+        self.intersect_with_aa_plane(Plane::Left);
+        self.intersect_with_aa_plane(Plane::Right);
+        self.intersect_with_aa_plane(Plane::Near);
+        self.intersect_with_aa_plane(Plane::Far);
+    }
+
+    /// Counters of everything past the traced rays, when they are collected.
+    #[inline(always)]
+    fn detailed_counters(&mut self) -> Option<&mut TraceCounters> {
+        if !self.collect_stats {
+            return None;
+        }
+        self.counters.as_deref_mut()
+    }
+
+    /// Intersection tests run on every lane, the ones of finished rays are wasted.
+    #[inline(always)]
+    fn count_intersection_test(&mut self) {
+        if !self.collect_stats {
+            return;
+        }
+        let live_lanes = -self.live_mask().to_int().reduce_sum() as u64;
+        if let Some(counters) = self.detailed_counters() {
+            counters.simd_ops += 1;
+            counters.active_lanes += live_lanes;
+        }
+    }
+
+    #[inline(always)]
     fn intersect_with_aa_plane(&mut self, plane: Plane) {
+        self.count_intersection_test();
         let axis = self.scene.plane_alignment_axis(plane);
         let offset_within_axis = Reals::splat(self.scene.plane_offset(plane));
         let normal = Vectors::from_single(self.scene.plane_normal(plane));
         let color = Colors::from_single(self.scene.obstacle_color(Obstacle::Plane(plane)));
         let reflectance = Reals::splat(self.scene.obstacle_reflectance(Obstacle::Plane(plane)));
+        let roughness = Reals::splat(self.scene.obstacle_roughness(Obstacle::Plane(plane)));
 
         let toi =
             (offset_within_axis - self.rays.origins.get_axis(axis)) / self.rays.dirs.get_axis(axis);
@@ -68,9 +246,14 @@ impl<'a> RaysProjections<'a> {
         update_reals_if(&mut self.min_toi, mask, toi);
         self.obstacle_colors.update_if(mask, color);
         update_reals_if(&mut self.obstacle_reflectances, mask, reflectance);
+        update_reals_if(&mut self.obstacle_roughnesses, mask, roughness);
+        self.obstacle_ids = mask.select(
+            Integers::splat(Obstacle::Plane(plane).id() as Integer),
+            self.obstacle_ids,
+        );
 
-        let mut offset_pois =
-            self.rays.origins + self.rays.dirs * toi + Points::splat(1000.0, 1000.0, 1000.0);
+        let pois = self.rays.origins + self.rays.dirs * toi;
+        let mut offset_pois = pois + Points::splat(1000.0, 1000.0, 1000.0);
         offset_pois *= 1.5;
         let checkered_mask = mask
             & ((offset_pois.xs.cast::<Integer>()
@@ -79,16 +262,92 @@ impl<'a> RaysProjections<'a> {
                 % Integers::splat(2))
             .simd_eq(Integers::splat(0));
 
-        update_reals_if(&mut self.obstacle_reflectances, checkered_mask, ZEROS);
+        update_reals_if(&mut self.obstacle_reflectances, checkered_mask, zeros());
+
+        if self.scene.wall_markers() {
+            let ball_pos = self.ball_positions();
+            let ball_radius = self.scene.sphere_radius(Sphere::Ball);
+            // Distances from the ball's projection, along the plane.
+            let deltas = pois - ball_pos;
+            let along_axis = *deltas.get_axis(axis);
+            let marker_mask = mask
+                & (deltas.dot(deltas) - along_axis * along_axis)
+                    .simd_lt(Reals::splat(ball_radius * ball_radius));
+            self.obstacle_colors
+                .update_if(marker_mask, color * WALL_MARKER_SHADE);
+            update_reals_if(&mut self.obstacle_reflectances, marker_mask, zeros());
+        }
+
+        if let (Some(ring_color), Axis::XS | Axis::YS) = (self.scene.depth_ring(), axis) {
+            let depth_deltas = pois.zs - self.ball_positions().zs;
+            let ring_mask = mask
+                & (depth_deltas * depth_deltas)
+                    .simd_lt(Reals::splat(DEPTH_RING_WIDTH * DEPTH_RING_WIDTH / 4.0));
+            self.obstacle_colors.update_if(
+                ring_mask,
+                Colors::from_single(ring_color) * DEPTH_RING_INTENSITY,
+            );
+            update_reals_if(&mut self.obstacle_reflectances, ring_mask, zeros());
+        }
 
         self.obstacle_normals.update_if(mask, normal);
     }
 
+    /// The ball's center at the time of every ray.
+    #[inline(always)]
+    fn ball_positions(&self) -> Points<N> {
+        Points::from_single(self.scene.sphere_pos(Sphere::Ball))
+            + Vectors::from_single(self.scene.sphere_shift(Sphere::Ball)) * self.time_offsets
+    }
+
+    /// Lights up primary rays passing by the outline of the ball at the scene's intercept, in
+    /// front of what they hit.
+    #[inline(always)]
+    fn draw_intercept_ghost(&mut self) {
+        let intercept = match self.scene.intercept() {
+            Some(intercept) => intercept,
+            None => return,
+        };
+        let radius = self.scene.sphere_radius(Sphere::Ball);
+        let deltas = Points::from_single(intercept) - self.rays.origins;
+        let tois = deltas.dot(self.rays.dirs) / self.rays.dirs.dot(self.rays.dirs);
+        let misses = self.rays.dirs * tois - deltas;
+        let distances_squared = misses.dot(misses);
+        let (inner, outer) = (
+            radius - GHOST_THICKNESS / 2.0,
+            radius + GHOST_THICKNESS / 2.0,
+        );
+        let mask = tois.simd_gt(zeros())
+            & tois.simd_lt(self.min_toi)
+            & distances_squared.simd_gt(Reals::splat(inner * inner))
+            & distances_squared.simd_lt(Reals::splat(outer * outer));
+        if !mask.any() {
+            return;
+        }
+        let mut glow = Colors::from_single(GHOST_COLOR) * self.coef_colors;
+        glow.update_if(!mask, Points::ZERO);
+        self.offset_colors += glow;
+    }
+
+    #[inline(always)]
+    fn count_sphere_early_exit(&mut self, stage: usize) {
+        if let Some(counters) = self.detailed_counters() {
+            counters.sphere_early_exits[stage] += 1;
+        }
+    }
+
+    #[inline(always)]
     fn intersect_with_sphere(&mut self, sphere: Sphere) {
-        let sphere_pos = Colors::from_single(self.scene.sphere_pos(sphere));
+        self.count_intersection_test();
+        if let Some(counters) = self.detailed_counters() {
+            counters.sphere_tests += 1;
+        }
+        let sphere_pos = Points::from_single(self.scene.sphere_pos(sphere))
+            + Vectors::from_single(self.scene.sphere_shift(sphere)) * self.time_offsets;
         let sphere_radius = self.scene.sphere_radius(sphere);
         let color = Colors::from_single(self.scene.obstacle_color(Obstacle::Sphere(sphere)));
         let reflectance = Reals::splat(self.scene.obstacle_reflectance(Obstacle::Sphere(sphere)));
+        let roughness = Reals::splat(self.scene.obstacle_roughness(Obstacle::Sphere(sphere)));
 
         let deltas = self.rays.origins - sphere_pos;
 
@@ -98,31 +357,34 @@ impl<'a> RaysProjections<'a> {
         let mut d = r_squared * dirs_squared_sum;
         let a = self.rays.dirs.xs * deltas.ys - self.rays.dirs.ys * deltas.xs;
         d -= a * a;
-        if !d.simd_ge(ZEROS).any() {
+        if !d.simd_ge(zeros()).any() {
+            self.count_sphere_early_exit(0);
             return;
         }
         let b = self.rays.dirs.xs * deltas.zs - self.rays.dirs.zs * deltas.xs;
         d -= b * b;
-        if !d.simd_ge(ZEROS).any() {
+        if !d.simd_ge(zeros()).any() {
+            self.count_sphere_early_exit(1);
             return;
         }
         let c = self.rays.dirs.ys * deltas.zs - self.rays.dirs.zs * deltas.ys;
         d -= c * c;
 
-        let mask = d.simd_ge(ZEROS);
+        let mask = d.simd_ge(zeros());
         if !mask.any() {
+            self.count_sphere_early_exit(2);
             return;
         }
 
-        d = d.simd_max(ZEROS);
-        let tts = ZEROS
+        d = d.simd_max(zeros());
+        let tts = zeros()
             - deltas.xs * self.rays.dirs.xs
             - deltas.ys * self.rays.dirs.ys
             - deltas.zs * self.rays.dirs.zs;
         let mut t1s = (tts + d.sqrt()) / dirs_squared_sum;
         let mut t2s = (tts - d.sqrt()) / dirs_squared_sum;
-        t1s = t1s.simd_max(ZEROS);
-        t2s = t2s.simd_max(ZEROS);
+        t1s = t1s.simd_max(zeros());
+        t2s = t2s.simd_max(zeros());
         let toi = t1s.simd_min(t2s);
         let mask = mask & toi.simd_gt(Reals::splat(MIN_TOI)) & toi.simd_lt(self.min_toi);
 
@@ -136,24 +398,63 @@ impl<'a> RaysProjections<'a> {
 
         self.obstacle_normals.update_if(mask, normals);
         update_reals_if(&mut self.obstacle_reflectances, mask, reflectance);
+        update_reals_if(&mut self.obstacle_roughnesses, mask, roughness);
+        self.obstacle_ids = mask.select(
+            Integers::splat(Obstacle::Sphere(sphere).id() as Integer),
+            self.obstacle_ids,
+        );
+    }
+
+    /// Lanes whose rays still carry some light.
+    #[inline(always)]
+    fn live_mask(&self) -> Mask<N> {
+        self.coef_colors.xs.simd_gt(zeros())
+            | self.coef_colors.ys.simd_gt(zeros())
+            | self.coef_colors.zs.simd_gt(zeros())
     }
 
+    #[inline(always)]
     fn reflect(&mut self) -> bool {
+        if self.depth == 0 {
+            self.primary_tois = self.min_toi;
+            self.primary_normals = self.obstacle_normals;
+            self.primary_ids = self.obstacle_ids;
+            self.draw_intercept_ghost();
+        }
+        let live_mask = self.live_mask();
+        self.bounces += live_mask.select(Integers::splat(1), Integers::splat(0));
+        if let Some(counters) = self.counters.as_deref_mut() {
+            let live_lanes = -live_mask.to_int().reduce_sum() as u64;
+            counters.count_rays(self.depth, live_lanes);
+        }
+        self.depth += 1;
+
         self.offset_colors += self.coef_colors * self.obstacle_colors;
         self.coef_colors *= self.obstacle_reflectances;
 
         self.depth_left -= 1;
 
-        if self.depth_left == 0 || self.obstacle_reflectances.simd_eq(ZEROS).all() {
+        // Rays that stopped reflecting any light are done, even if others in the packet aren't.
+        if self.depth_left == 0 || !self.live_mask().any() {
             return true;
         }
 
         let pois = self.rays.origins + self.rays.dirs * self.min_toi;
-        let reflection_dirs = self.rays.dirs
+        let mut reflection_dirs = self.rays.dirs
             - (self.obstacle_normals
                 * self.rays.dirs.dot(self.obstacle_normals)
                 * Reals::splat(2.0));
 
+        let rough_mask = self.obstacle_roughnesses.simd_gt(zeros());
+        if rough_mask.any() {
+            let lengths = reflection_dirs.length();
+            let glossy_dirs = reflection_dirs
+                + self.random.next_on_sphere() * (self.obstacle_roughnesses * lengths);
+            // Perturbed directions going under the surface fall back to the mirror ones.
+            let above_mask = rough_mask & glossy_dirs.dot(self.obstacle_normals).simd_gt(zeros());
+            reflection_dirs.update_if(above_mask, glossy_dirs);
+        }
+
         self.rays = Rays::new(pois, reflection_dirs);
 
         self.min_toi = Reals::splat(std::f32::MAX);
@@ -161,7 +462,44 @@ impl<'a> RaysProjections<'a> {
         false
     }
 
-    fn finish(mut self, base_colors: Colors) -> Colors {
+    #[inline(always)]
+    fn visualize(self, mode: RenderMode, max_depth: usize) -> Colors<N> {
+        match mode {
+            RenderMode::Shaded => unreachable!(),
+            RenderMode::Normals => self.primary_normals * 0.5 + Colors::splat(0.5, 0.5, 0.5),
+            RenderMode::Depth => {
+                let brightness = Reals::splat(1.0)
+                    - (self.primary_tois / Reals::splat(DEBUG_MAX_DEPTH))
+                        .simd_min(Reals::splat(1.0));
+                Colors {
+                    xs: brightness,
+                    ys: brightness,
+                    zs: brightness,
+                }
+            }
+            RenderMode::ObstacleIds => {
+                let mut colors = Points::ZERO;
+                for (id, &color) in OBSTACLE_IDS_COLORS.iter().enumerate() {
+                    colors.update_if(
+                        self.primary_ids.simd_eq(Integers::splat(id as Integer)),
+                        Colors::from_single(color),
+                    );
+                }
+                colors
+            }
+            RenderMode::Bounces => {
+                let share = self.bounces.cast::<f32>() / Reals::splat(max_depth as f32);
+                Colors {
+                    xs: share,
+                    ys: zeros(),
+                    zs: Reals::splat(1.0) - share,
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn finish(mut self, base_colors: Colors<N>) -> Colors<N> {
         self.coef_colors *= base_colors;
         self.offset_colors += self.coef_colors;
         self.offset_colors
@@ -24,6 +24,17 @@ pub enum Obstacle {
 }
 
 const PLANES_COUNT: usize = Plane::Near as usize + 1;
+pub const OBSTACLES_COUNT: usize = PLANES_COUNT + SPHERES_COUNT;
+
+impl Obstacle {
+    /// Unique among all obstacles, below `OBSTACLES_COUNT`.
+    pub fn id(&self) -> usize {
+        match self {
+            Obstacle::Plane(plane) => *plane as usize,
+            Obstacle::Sphere(sphere) => PLANES_COUNT + *sphere as usize,
+        }
+    }
+}
 
 const PLANES_OFFSETS: [Real; PLANES_COUNT] = [2.0, -2.0, -4.0, 4.0, -16.0, 0.0];
 const PLANES_NORMALS: [Vector; PLANES_COUNT] = [
@@ -45,6 +56,7 @@ const PLANES_COLORS: [Color; PLANES_COUNT] = [
     Color::new(0.1, 0.8, 0.1),
 ];
 const PLANES_REFLECTANCE: [Real; PLANES_COUNT] = [0.3, 0.3, 0.3, 0.3, 0.3, 0.3];
+const PLANES_ROUGHNESS: [Real; PLANES_COUNT] = [0.0, 0.08, 0.0, 0.0, 0.0, 0.0];
 
 const SPHERES_COUNT: usize = Sphere::FarPaddle as usize + 1;
 const SPHERES_RADII: [Real; SPHERES_COUNT] = [0.5, 4.0, 4.0];
@@ -54,19 +66,33 @@ const SPHERES_COLORS: [Color; SPHERES_COUNT] = [
     Color::new(0.0, 0.0, 0.0),
 ];
 const SPHERES_REFLECTANCE: [Real; SPHERES_COUNT] = [0.5, 1.0, 1.0];
+const SPHERES_ROUGHNESS: [Real; SPHERES_COUNT] = [0.0, 0.04, 0.04];
 
 pub struct Scene {
     ball_pos: Point,
     near_paddle_pos: Point,
     far_paddle_pos: Point,
+    previous_positions: [Point; SPHERES_COUNT],
+    // Gameplay aids the tracer draws: the ball's shadow on every wall, where it's predicted to
+    // reach the near paddle and a ring around the room at its depth.
+    wall_markers: bool,
+    intercept: Option<Point>,
+    depth_ring: Option<Color>,
 }
 
 impl Scene {
     pub fn new() -> Scene {
+        let ball_pos = Point::new(-2.0, -1.0, -6.0);
+        let near_paddle_pos = Point::new(0.0, 0.0, 3.87);
+        let far_paddle_pos = Point::new(0.0, 0.0, -19.87);
         Scene {
-            ball_pos: Point::new(-2.0, -1.0, -6.0),
-            near_paddle_pos: Point::new(0.0, 0.0, 3.87),
-            far_paddle_pos: Point::new(0.0, 0.0, -19.87),
+            ball_pos,
+            near_paddle_pos,
+            far_paddle_pos,
+            previous_positions: [ball_pos, near_paddle_pos, far_paddle_pos],
+            wall_markers: false,
+            intercept: None,
+            depth_ring: None,
         }
     }
 
@@ -82,6 +108,11 @@ impl Scene {
         }
     }
 
+    /// How far the sphere has moved since the last `remember_positions`.
+    pub fn sphere_shift(&self, sphere: Sphere) -> Vector {
+        self.sphere_pos(sphere) - self.previous_positions[sphere as usize]
+    }
+
     pub fn sphere_radius(&self, sphere: Sphere) -> Real {
         SPHERES_RADII[sphere as usize]
     }
@@ -108,6 +139,59 @@ impl Scene {
         }
     }
 
+    pub fn obstacle_roughness(&self, obstacle: Obstacle) -> Real {
+        match obstacle {
+            Obstacle::Plane(plane) => PLANES_ROUGHNESS[plane as usize],
+            Obstacle::Sphere(sphere) => SPHERES_ROUGHNESS[sphere as usize],
+        }
+    }
+
+    pub fn wall_markers(&self) -> bool {
+        self.wall_markers
+    }
+
+    /// Shades the ball's projection on every wall, showing its position along the other two
+    /// axes.
+    pub fn set_wall_markers(&mut self, wall_markers: bool) {
+        self.wall_markers = wall_markers;
+    }
+
+    pub fn intercept(&self) -> Option<Point> {
+        self.intercept
+    }
+
+    /// Draws a ghost of the ball at `intercept`, none when `None`.
+    pub fn set_intercept(&mut self, intercept: Option<Point>) {
+        self.intercept = intercept;
+    }
+
+    pub fn depth_ring(&self) -> Option<Color> {
+        self.depth_ring
+    }
+
+    /// Lights up a band of this color across the four side walls at the ball's depth, none
+    /// when `None`.
+    pub fn set_depth_ring(&mut self, depth_ring: Option<Color>) {
+        self.depth_ring = depth_ring;
+    }
+
+    /// Positions of the ball and the near and far paddles.
+    pub fn sphere_positions(&self) -> [Point; SPHERES_COUNT] {
+        [Sphere::Ball, Sphere::NearPaddle, Sphere::FarPaddle].map(|sphere| self.sphere_pos(sphere))
+    }
+
+    /// Makes `sphere_shift` span from `positions`, taken with `sphere_positions`, to blur the
+    /// motion of several ticks.
+    pub fn set_previous_positions(&mut self, positions: [Point; SPHERES_COUNT]) {
+        self.previous_positions = positions;
+    }
+
+    pub fn remember_positions(&mut self) {
+        for sphere in [Sphere::Ball, Sphere::NearPaddle, Sphere::FarPaddle] {
+            self.previous_positions[sphere as usize] = self.sphere_pos(sphere);
+        }
+    }
+
     pub fn move_sphere_to(&mut self, sphere: Sphere, position: Point) {
         let sphere_pos = match sphere {
             Sphere::Ball => &mut self.ball_pos,
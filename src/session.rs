@@ -0,0 +1,205 @@
+//! Games recorded as the steps their simulation took, for replaying them exactly later.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::math::Directions;
+
+const HEADER: &str = "ray-ten session 1";
+// Letters of up, down, left and right in recorded directions, `-` standing for ones not held.
+const DIRECTION_LETTERS: [char; 4] = ['u', 'd', 'l', 'r'];
+
+/// Arguments of a `GameState::advance` call.
+#[derive(Clone, Copy)]
+pub struct Step {
+    pub elapsed: f32,
+    pub near_directions: Directions,
+    pub far_directions: Directions,
+}
+
+/// Writes every step of a game started with `GameState::new` or `restart` into a file, a line
+/// per step. The last ones reach the file when the recorder is dropped.
+pub struct SessionRecorder {
+    path: String,
+    file: BufWriter<File>,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &str) -> Result<SessionRecorder, String> {
+        let file = File::create(path).map_err(|err| format!("can't create '{}': {}", path, err))?;
+        let mut recorder = SessionRecorder {
+            path: path.to_string(),
+            file: BufWriter::new(file),
+        };
+        recorder.write_line(HEADER)?;
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn record(&mut self, step: Step) -> Result<(), String> {
+        self.write_line(&format_step(step))
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.file, "{}", line)
+            .map_err(|err| format!("can't write '{}': {}", self.path, err))
+    }
+}
+
+impl Drop for SessionRecorder {
+    // `BufWriter` flushes on its own when dropped, but silently drops the errors.
+    fn drop(&mut self) {
+        if let Err(err) = self.file.flush() {
+            eprintln!("can't write '{}': {}", self.path, err);
+        }
+    }
+}
+
+/// Steps of a session written by `SessionRecorder`.
+pub fn load(path: &str) -> Result<Vec<Step>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read '{}': {}", path, err))?;
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(format!("'{}' isn't a recorded session", path));
+    }
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            parse_step(line).ok_or_else(|| format!("invalid step on line {} of '{}'", i + 2, path))
+        })
+        .collect()
+}
+
+fn format_step(step: Step) -> String {
+    // Floats print as the shortest text parsing back to the same value.
+    format!(
+        "{} {} {}",
+        step.elapsed,
+        format_directions(step.near_directions),
+        format_directions(step.far_directions)
+    )
+}
+
+fn parse_step(line: &str) -> Option<Step> {
+    let mut fields = line.split(' ');
+    let elapsed: f32 = fields.next()?.parse().ok()?;
+    // Replays advance until the frame's time is reached, which no other value would do.
+    if !elapsed.is_finite() || elapsed <= 0.0 {
+        return None;
+    }
+    let step = Step {
+        elapsed,
+        near_directions: parse_directions(fields.next()?)?,
+        far_directions: parse_directions(fields.next()?)?,
+    };
+    match fields.next() {
+        Some(_) => None,
+        None => Some(step),
+    }
+}
+
+fn format_directions(directions: Directions) -> String {
+    DIRECTION_LETTERS
+        .iter()
+        .zip(directions.to_array())
+        .map(|(&letter, held)| if held { letter } else { '-' })
+        .collect()
+}
+
+fn parse_directions(text: &str) -> Option<Directions> {
+    let mut held = [false; 4];
+    if text.chars().count() != held.len() {
+        return None;
+    }
+    for ((held, letter), &expected) in held.iter_mut().zip(text.chars()).zip(&DIRECTION_LETTERS) {
+        *held = match letter {
+            '-' => false,
+            letter if letter == expected => true,
+            _ => return None,
+        };
+    }
+    Some(Directions::new(held[0], held[1], held[2], held[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_round_trip() {
+        let directions = [
+            Directions::new(false, false, false, false),
+            Directions::new(true, false, false, true),
+            Directions::new(false, true, true, false),
+            Directions::new(true, true, true, true),
+        ];
+        for (i, &elapsed) in [1.0 / 60.0, 0.1, 1e-7, 2.5].iter().enumerate() {
+            let step = Step {
+                elapsed,
+                near_directions: directions[i],
+                far_directions: directions[3 - i],
+            };
+            let parsed = parse_step(&format_step(step)).unwrap();
+            assert_eq!(parsed.elapsed, step.elapsed);
+            assert_eq!(
+                parsed.near_directions.to_array(),
+                step.near_directions.to_array()
+            );
+            assert_eq!(
+                parsed.far_directions.to_array(),
+                step.far_directions.to_array()
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_sessions_load_back() {
+        let path = std::env::temp_dir().join(format!("ray-ten-session-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let steps = [
+            (0.25, Directions::new(true, false, false, true)),
+            (1.0 / 60.0, Directions::new(false, false, false, false)),
+            (1.0 / 30.0, Directions::new(false, true, true, false)),
+        ];
+        {
+            let mut recorder = SessionRecorder::create(path).unwrap();
+            for &(elapsed, directions) in &steps {
+                recorder
+                    .record(Step {
+                        elapsed,
+                        near_directions: directions,
+                        far_directions: Directions::new(false, false, true, false),
+                    })
+                    .unwrap();
+            }
+        }
+        let loaded = load(path);
+        std::fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.len(), steps.len());
+        for (step, &(elapsed, directions)) in loaded.iter().zip(&steps) {
+            assert_eq!(step.elapsed, elapsed);
+            assert_eq!(step.near_directions.to_array(), directions.to_array());
+            assert_eq!(step.far_directions.to_array(), [false, false, true, false]);
+        }
+    }
+
+    #[test]
+    fn invalid_steps_are_rejected() {
+        for line in [
+            "0 ---- ----",
+            "-0.1 ---- ----",
+            "NaN ---- ----",
+            "inf ---- ----",
+            "0.1 ---- ---",
+            "0.1 du-- ----",
+            "0.1 ---- ---- ----",
+        ] {
+            assert!(parse_step(line).is_none(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Plain array stand-ins for the parts of `std::simd` the tracer uses, for stable compilers.
+//!
+//! Every operation is an element-wise loop over a fixed size array, which LLVM turns into
+//! vector instructions just fine once inlined. Integer arithmetic wraps, like in `std::simd`.
+
+use std::marker::PhantomData;
+use std::ops::{
+    Add, AddAssign, BitAnd, BitOr, BitXor, BitXorAssign, Div, DivAssign, Index, IndexMut, Mul,
+    MulAssign, Neg, Not, Rem, Shl, Shr, Sub, SubAssign,
+};
+
+pub struct LaneCount<const N: usize>;
+
+pub trait SupportedLaneCount {}
+
+impl SupportedLaneCount for LaneCount<1> {}
+impl SupportedLaneCount for LaneCount<2> {}
+impl SupportedLaneCount for LaneCount<4> {}
+impl SupportedLaneCount for LaneCount<8> {}
+impl SupportedLaneCount for LaneCount<16> {}
+impl SupportedLaneCount for LaneCount<32> {}
+impl SupportedLaneCount for LaneCount<64> {}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Simd<T, const N: usize>([T; N]);
+
+impl<T: Copy, const N: usize> Simd<T, N> {
+    pub const fn from_array(array: [T; N]) -> Self {
+        Simd(array)
+    }
+
+    #[inline]
+    pub fn splat(value: T) -> Self {
+        Simd([value; N])
+    }
+
+    #[inline]
+    pub fn cast<U>(self) -> Simd<U, N>
+    where
+        T: Cast<U>,
+    {
+        self.map(Cast::cast)
+    }
+
+    #[inline]
+    fn map<U>(self, f: impl Fn(T) -> U) -> Simd<U, N> {
+        Simd(self.0.map(f))
+    }
+
+    #[inline]
+    fn zip<U>(self, other: Self, f: impl Fn(T, T) -> U) -> Simd<U, N> {
+        Simd(std::array::from_fn(|i| f(self.0[i], other.0[i])))
+    }
+}
+
+impl<T, const N: usize> Index<usize> for Simd<T, N> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.0[index]
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for Simd<T, N> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.0[index]
+    }
+}
+
+/// Element conversions of `Simd::cast`, with the semantics of `as`.
+pub trait Cast<U> {
+    fn cast(self) -> U;
+}
+
+macro_rules! impl_cast {
+    ($($from:ty => $($to:ty),*;)*) => {
+        $($(
+            impl Cast<$to> for $from {
+                #[inline]
+                fn cast(self) -> $to {
+                    self as $to
+                }
+            }
+        )*)*
+    };
+}
+
+impl_cast! {
+    f32 => f32, i32, u32;
+    i32 => f32, i32, u32;
+    u32 => f32, i32, u32;
+}
+
+macro_rules! impl_binary_op {
+    ($op:ident, $method:ident, $($element:ty => $element_method:path),*) => {
+        $(
+            impl<const N: usize> $op for Simd<$element, N> {
+                type Output = Self;
+
+                #[inline]
+                fn $method(self, rhs: Self) -> Self {
+                    self.zip(rhs, $element_method)
+                }
+            }
+
+            impl<const N: usize> $op<&Self> for Simd<$element, N> {
+                type Output = Self;
+
+                #[inline]
+                fn $method(self, rhs: &Self) -> Self {
+                    self.zip(*rhs, $element_method)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_assign_op {
+    ($op:ident, $method:ident, $binary_method:ident, $($element:ty),*) => {
+        $(
+            impl<const N: usize> $op for Simd<$element, N> {
+                #[inline]
+                fn $method(&mut self, rhs: Self) {
+                    *self = self.$binary_method(rhs);
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_op!(Add, add, f32 => Add::add, i32 => i32::wrapping_add, u32 => u32::wrapping_add);
+impl_binary_op!(Sub, sub, f32 => Sub::sub, i32 => i32::wrapping_sub, u32 => u32::wrapping_sub);
+impl_binary_op!(Mul, mul, f32 => Mul::mul, i32 => i32::wrapping_mul, u32 => u32::wrapping_mul);
+impl_binary_op!(Div, div, f32 => Div::div, i32 => i32::wrapping_div, u32 => u32::wrapping_div);
+impl_binary_op!(Rem, rem, f32 => Rem::rem, i32 => i32::wrapping_rem, u32 => u32::wrapping_rem);
+impl_binary_op!(BitXor, bitxor, i32 => BitXor::bitxor, u32 => BitXor::bitxor);
+impl_binary_op!(BitOr, bitor, i32 => BitOr::bitor, u32 => BitOr::bitor);
+impl_binary_op!(BitAnd, bitand, i32 => BitAnd::bitand, u32 => BitAnd::bitand);
+impl_binary_op!(Shl, shl, u32 => u32::wrapping_shl);
+impl_binary_op!(Shr, shr, u32 => u32::wrapping_shr);
+
+impl_assign_op!(AddAssign, add_assign, add, f32, i32, u32);
+impl_assign_op!(SubAssign, sub_assign, sub, f32, i32, u32);
+impl_assign_op!(MulAssign, mul_assign, mul, f32, i32, u32);
+impl_assign_op!(DivAssign, div_assign, div, f32, i32, u32);
+impl_assign_op!(BitXorAssign, bitxor_assign, bitxor, i32, u32);
+
+impl<const N: usize> Neg for Simd<f32, N> {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        self.map(Neg::neg)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Mask<T, const N: usize>([i32; N], PhantomData<T>);
+
+// Derived ones would require `T: Copy`.
+impl<T, const N: usize> Clone for Mask<T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const N: usize> Copy for Mask<T, N> {}
+
+impl<T, const N: usize> Mask<T, N> {
+    #[inline]
+    pub fn select<U: Copy>(self, true_values: Simd<U, N>, false_values: Simd<U, N>) -> Simd<U, N> {
+        Simd(std::array::from_fn(|i| {
+            if self.0[i] != 0 {
+                true_values.0[i]
+            } else {
+                false_values.0[i]
+            }
+        }))
+    }
+
+    #[inline]
+    pub fn any(self) -> bool {
+        self.0.iter().fold(0, |any, &lane| any | lane) != 0
+    }
+
+    /// `-1` for set lanes, `0` for the others.
+    #[inline]
+    pub fn to_int(self) -> Simd<i32, N> {
+        Simd(self.0)
+    }
+
+    #[inline]
+    fn zip(self, other: Self, f: impl Fn(i32, i32) -> i32) -> Self {
+        Mask(
+            std::array::from_fn(|i| f(self.0[i], other.0[i])),
+            PhantomData,
+        )
+    }
+}
+
+impl<T, const N: usize> BitAnd for Mask<T, N> {
+    type Output = Self;
+
+    #[inline]
+    fn bitand(self, rhs: Self) -> Self {
+        self.zip(rhs, BitAnd::bitand)
+    }
+}
+
+impl<T, const N: usize> BitOr for Mask<T, N> {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        self.zip(rhs, BitOr::bitor)
+    }
+}
+
+impl<T, const N: usize> Not for Mask<T, N> {
+    type Output = Self;
+
+    #[inline]
+    fn not(self) -> Self {
+        Mask(self.0.map(Not::not), PhantomData)
+    }
+}
+
+pub trait SimdPartialEq {
+    type Mask;
+
+    fn simd_eq(self, other: Self) -> Self::Mask;
+}
+
+pub trait SimdPartialOrd: SimdPartialEq {
+    fn simd_lt(self, other: Self) -> Self::Mask;
+    fn simd_gt(self, other: Self) -> Self::Mask;
+    fn simd_ge(self, other: Self) -> Self::Mask;
+}
+
+macro_rules! impl_comparison {
+    ($($method:ident => $op:tt),*) => {
+        $(
+            #[inline]
+            fn $method(self, other: Self) -> Self::Mask {
+                Mask(
+                    std::array::from_fn(|i| -((self.0[i] $op other.0[i]) as i32)),
+                    PhantomData,
+                )
+            }
+        )*
+    };
+}
+
+impl<T: Copy + PartialOrd, const N: usize> SimdPartialEq for Simd<T, N> {
+    type Mask = Mask<i32, N>;
+
+    impl_comparison!(simd_eq => ==);
+}
+
+impl<T: Copy + PartialOrd, const N: usize> SimdPartialOrd for Simd<T, N> {
+    impl_comparison!(simd_lt => <, simd_gt => >, simd_ge => >=);
+}
+
+pub trait SimdFloat {
+    fn simd_max(self, other: Self) -> Self;
+    fn simd_min(self, other: Self) -> Self;
+}
+
+impl<const N: usize> SimdFloat for Simd<f32, N> {
+    #[inline]
+    fn simd_max(self, other: Self) -> Self {
+        self.zip(other, f32::max)
+    }
+
+    #[inline]
+    fn simd_min(self, other: Self) -> Self {
+        self.zip(other, f32::min)
+    }
+}
+
+pub trait SimdInt {
+    fn reduce_sum(self) -> i32;
+}
+
+impl<const N: usize> SimdInt for Simd<i32, N> {
+    #[inline]
+    fn reduce_sum(self) -> i32 {
+        self.0.iter().fold(0, |sum, &lane| sum.wrapping_add(lane))
+    }
+}
+
+pub trait StdFloat {
+    fn sqrt(self) -> Self;
+    fn floor(self) -> Self;
+}
+
+impl<const N: usize> StdFloat for Simd<f32, N> {
+    #[inline]
+    fn sqrt(self) -> Self {
+        self.map(f32::sqrt)
+    }
+
+    #[inline]
+    fn floor(self) -> Self {
+        self.map(f32::floor)
+    }
+}
+
+// Nightly compilers build the module for its tests only, checking it against `std::simd`.
+#[cfg(all(test, not(feature = "stable")))]
+mod tests {
+    use super::*;
+    use std::simd::{SimdFloat as _, SimdPartialEq as _, SimdPartialOrd as _, StdFloat as _};
+
+    const LANES: usize = 8;
+    const XS: [f32; LANES] = [0.0, -0.0, 1.5, -2.25, 1e-30, 3e38, f32::INFINITY, f32::NAN];
+    const YS: [f32; LANES] = [-0.0, 0.5, 1.5, 7.0, -1e30, 3e38, 2.0, 1.0];
+
+    type StdReals = std::simd::Simd<f32, LANES>;
+
+    fn reals(xs: [f32; LANES]) -> (Simd<f32, LANES>, StdReals) {
+        (Simd::from_array(xs), StdReals::from_array(xs))
+    }
+
+    fn assert_same(operation: &str, stable: Simd<f32, LANES>, std: StdReals) {
+        for (lane, (x, y)) in stable.0.iter().zip(std.to_array()).enumerate() {
+            assert!(
+                x == &y || (x.is_nan() && y.is_nan()),
+                "{} differs on lane {}: {} and {}",
+                operation,
+                lane,
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_matches_std_simd() {
+        let ((xs, std_xs), (ys, std_ys)) = (reals(XS), reals(YS));
+        assert_same("add", xs + ys, std_xs + std_ys);
+        assert_same("sub", xs - ys, std_xs - std_ys);
+        assert_same("mul", xs * ys, std_xs * std_ys);
+        assert_same("div", xs / ys, std_xs / std_ys);
+        assert_same("rem", xs % ys, std_xs % std_ys);
+        assert_same("neg", -xs, -std_xs);
+        assert_same("max", xs.simd_max(ys), std_xs.simd_max(std_ys));
+        assert_same("min", xs.simd_min(ys), std_xs.simd_min(std_ys));
+        assert_same("sqrt", xs.sqrt(), std_xs.sqrt());
+        assert_same("floor", xs.floor(), std_xs.floor());
+        assert_same("cast", xs.cast::<i32>().cast(), std_xs.cast::<i32>().cast());
+    }
+
+    #[test]
+    fn comparisons_match_std_simd() {
+        let ((xs, std_xs), (ys, std_ys)) = (reals(XS), reals(YS));
+        let (ones, std_ones) = reals([1.0; LANES]);
+        let (zeros, std_zeros) = reals([0.0; LANES]);
+        let masks = [
+            (xs.simd_eq(ys), std_xs.simd_eq(std_ys)),
+            (xs.simd_lt(ys), std_xs.simd_lt(std_ys)),
+            (xs.simd_gt(ys), std_xs.simd_gt(std_ys)),
+            (xs.simd_ge(ys), std_xs.simd_ge(std_ys)),
+            (
+                xs.simd_lt(ys) | xs.simd_eq(ys),
+                std_xs.simd_lt(std_ys) | std_xs.simd_eq(std_ys),
+            ),
+            (
+                xs.simd_ge(ys) & !ys.simd_lt(zeros),
+                std_xs.simd_ge(std_ys) & !std_ys.simd_lt(std_zeros),
+            ),
+        ];
+        for (mask, std_mask) in masks {
+            assert_eq!(mask.any(), std_mask.any());
+            assert_same(
+                "select",
+                mask.select(ones, zeros),
+                std_mask.select(std_ones, std_zeros),
+            );
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Plays the game in a terminal, over SSH too, printing frames as truecolor half blocks or sixel
+//! images instead of opening a window.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, terminal};
+
+use crate::game_state::{control_far_paddle, AdvanceResult, GameState};
+use crate::math::Directions;
+
+const FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 30);
+// Terminals not reporting releases only repeat presses while keys are held, after a delay of up
+// to half a second and then every few tens of milliseconds, so directions stay on for this many
+// seconds after a first press and after a repeated one.
+const KEY_HOLD: f32 = 0.5;
+const KEY_REPEAT_HOLD: f32 = 0.1;
+// Guess of a character cell's size in pixels, for sizing sixel frames to the terminal.
+const SIXEL_CELL_SIZE: (u16, u16) = (8, 16);
+// Channel levels of the color cube sixel frames are quantized to.
+const SIXEL_LEVELS: usize = 6;
+
+/// How frames get printed.
+#[derive(Clone, Copy)]
+pub enum Output {
+    /// A character per two pixels, the upper one in the foreground color of `▀` and the lower
+    /// one in the background color.
+    HalfBlocks,
+    /// A sixel image, `None` sizing it to the terminal.
+    Sixel(Option<(u16, u16)>),
+}
+
+/// Restores the terminal when the game ends, however it does, panics included as release
+/// builds abort on them without dropping anything.
+struct RawMode;
+
+impl RawMode {
+    fn enter() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(terminal_error)?;
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        // Terminals supporting the kitty keyboard protocol report key releases, the others
+        // ignore it.
+        execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )
+        .map_err(terminal_error)?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = execute!(
+        std::io::stdout(),
+        PopKeyboardEnhancementFlags,
+        cursor::Show,
+        terminal::LeaveAlternateScreen
+    );
+    let _ = terminal::disable_raw_mode();
+}
+
+fn terminal_error(err: std::io::Error) -> String {
+    format!("terminal error: {}", err)
+}
+
+/// Plays until `q`, escape or Ctrl-C, the arrow keys moving the near paddle.
+pub fn run(output: Output) -> Result<(), String> {
+    let _raw_mode = RawMode::enter()?;
+    let mut stdout = std::io::stdout();
+    let (mut width, mut height) = frame_size(output)?;
+    let mut game_state = GameState::new(width, height);
+    let mut buffer = vec![[0, 0, 0, 255]; width as usize * height as usize];
+    let mut frame = Vec::new();
+    let mut score = 0;
+    // Seconds every direction, up, down, left and right, stays held for.
+    let mut held = [0.0; 4];
+    // Set on the first release, directions stay held until theirs from then on.
+    let mut reports_releases = false;
+    let mut last_frame = Instant::now();
+
+    loop {
+        while event::poll(Duration::ZERO).map_err(terminal_error)? {
+            match event::read().map_err(terminal_error)? {
+                Event::Key(KeyEvent {
+                    code, modifiers, ..
+                }) if code == KeyCode::Char('q')
+                    || code == KeyCode::Esc
+                    || (code == KeyCode::Char('c')
+                        && modifiers.contains(KeyModifiers::CONTROL)) =>
+                {
+                    return Ok(());
+                }
+                Event::Key(KeyEvent { code, kind, .. }) => {
+                    let direction = match code {
+                        KeyCode::Up => 0,
+                        KeyCode::Down => 1,
+                        KeyCode::Left => 2,
+                        KeyCode::Right => 3,
+                        _ => continue,
+                    };
+                    held[direction] = match kind {
+                        KeyEventKind::Release => {
+                            reports_releases = true;
+                            0.0
+                        }
+                        _ if reports_releases => f32::INFINITY,
+                        _ if held[direction] > 0.0 => KEY_REPEAT_HOLD.max(held[direction]),
+                        _ => KEY_HOLD,
+                    };
+                }
+                Event::Resize(..) => {
+                    (width, height) = frame_size(output)?;
+                    game_state.resize(width, height);
+                    buffer.resize(width as usize * height as usize, [0, 0, 0, 255]);
+                    execute!(stdout, terminal::Clear(terminal::ClearType::All))
+                        .map_err(terminal_error)?;
+                }
+                _ => {}
+            }
+        }
+
+        let elapsed = last_frame.elapsed().as_secs_f32();
+        last_frame = Instant::now();
+        let near_directions =
+            Directions::new(held[0] > 0.0, held[1] > 0.0, held[2] > 0.0, held[3] > 0.0);
+        for held in &mut held {
+            *held -= elapsed;
+        }
+        let far_directions = control_far_paddle(&game_state.scene);
+        match game_state.advance(elapsed, near_directions, far_directions) {
+            AdvanceResult::PlayerMiss => score -= 1,
+            AdvanceResult::AIMiss => score += 1,
+            _ => {}
+        }
+        game_state.render(1.0, &mut buffer);
+
+        frame.clear();
+        frame.extend_from_slice(b"\x1b[H");
+        match output {
+            Output::HalfBlocks => write_half_blocks(&mut frame, &buffer, width as usize),
+            Output::Sixel(_) => write_sixel(&mut frame, &buffer, width as usize),
+        }
+        frame.extend_from_slice(format!("\r\n\x1b[0mScore: {}, q quits\x1b[K", score).as_bytes());
+        stdout
+            .write_all(&frame)
+            .and_then(|_| stdout.flush())
+            .map_err(terminal_error)?;
+
+        if let Some(left) = FRAME_TIME.checked_sub(last_frame.elapsed()) {
+            std::thread::sleep(left);
+        }
+    }
+}
+
+/// Pixels fitting the terminal above the status line.
+fn frame_size(output: Output) -> Result<(u16, u16), String> {
+    let (columns, rows) = terminal::size().map_err(terminal_error)?;
+    let rows = rows.saturating_sub(1).max(1);
+    Ok(match output {
+        Output::HalfBlocks => (columns.max(1), rows * 2),
+        Output::Sixel(Some(size)) => size,
+        Output::Sixel(None) => (
+            (columns * SIXEL_CELL_SIZE.0).max(1),
+            // Sixel images are printed in bands of 6 pixels.
+            rows * SIXEL_CELL_SIZE.1 / 6 * 6,
+        ),
+    })
+}
+
+fn write_half_blocks(frame: &mut Vec<u8>, buffer: &[[u8; 4]], width: usize) {
+    // Only prints colors when they change, which they rarely do along flat walls.
+    let mut colors = None;
+    for (row, pixels) in buffer.chunks(width * 2).enumerate() {
+        if row > 0 {
+            frame.extend_from_slice(b"\r\n");
+        }
+        let (upper, lower) = pixels.split_at(width.min(pixels.len()));
+        for (x, upper) in upper.iter().enumerate() {
+            let lower = lower.get(x).unwrap_or(upper);
+            if colors != Some((upper, lower)) {
+                colors = Some((upper, lower));
+                frame.extend_from_slice(
+                    format!(
+                        "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                        upper[0], upper[1], upper[2], lower[0], lower[1], lower[2]
+                    )
+                    .as_bytes(),
+                );
+            }
+            frame.extend_from_slice("▀".as_bytes());
+        }
+    }
+}
+
+fn write_sixel(frame: &mut Vec<u8>, buffer: &[[u8; 4]], width: usize) {
+    let height = buffer.len() / width;
+    let colors = SIXEL_LEVELS * SIXEL_LEVELS * SIXEL_LEVELS;
+    let level = |channel: u8| (channel as usize * (SIXEL_LEVELS - 1) + 127) / 255;
+    frame.extend_from_slice(format!("\x1bPq\"1;1;{};{}", width, height).as_bytes());
+    for color in 0..colors {
+        let percent = |level: usize| level * 100 / (SIXEL_LEVELS - 1);
+        frame.extend_from_slice(
+            format!(
+                "#{};2;{};{};{}",
+                color,
+                percent(color / (SIXEL_LEVELS * SIXEL_LEVELS)),
+                percent(color / SIXEL_LEVELS % SIXEL_LEVELS),
+                percent(color % SIXEL_LEVELS)
+            )
+            .as_bytes(),
+        );
+    }
+
+    // Six bits of every column in a band for every color, only allocated for the colors used.
+    let mut bands: Vec<Option<Vec<u8>>> = vec![None; colors];
+    for band in buffer.chunks(width * 6) {
+        for (i, pixel) in band.iter().enumerate() {
+            let color =
+                (level(pixel[0]) * SIXEL_LEVELS + level(pixel[1])) * SIXEL_LEVELS + level(pixel[2]);
+            bands[color].get_or_insert_with(|| vec![0; width])[i % width] |= 1 << (i / width);
+        }
+        for (color, bits) in bands.iter_mut().enumerate() {
+            if let Some(bits) = bits.take() {
+                frame.extend_from_slice(format!("#{}", color).as_bytes());
+                // Runs of the same bits are repeated with `!`.
+                let mut start = 0;
+                while start < width {
+                    let len = bits[start..]
+                        .iter()
+                        .take_while(|&&b| b == bits[start])
+                        .count();
+                    let sixel = bits[start] + b'?';
+                    if len > 3 {
+                        frame.extend_from_slice(format!("!{}{}", len, sixel as char).as_bytes());
+                    } else {
+                        frame.resize(frame.len() + len, sixel);
+                    }
+                    start += len;
+                }
+                frame.push(b'$');
+            }
+        }
+        frame.push(b'-');
+    }
+    frame.extend_from_slice(b"\x1b\\");
+}
@@ -23,6 +23,9 @@ const HUD_FONT_SCALE: f32 = 0.1;
 
 const DEBUG_FG_COLOR: Color = DARKGRAY;
 const DEBUG_FONT_SCALE: f32 = 0.05;
+const DEBUG_OVERLAY_FG_COLOR: Color = YELLOW;
+const DEBUG_MARKER_RADIUS: f32 = 8.0;
+const DEBUG_MARKER_THICKNESS: f32 = 2.0;
 
 const SHADE_OFFSET: f32 = 2.0;
 
@@ -207,3 +210,53 @@ pub fn show_debug_bottom_left(text: &str) {
         },
     );
 }
+
+pub fn show_debug_lines_top_left(lines: &[String]) {
+    let screen_height = screen_height();
+    let font_size = (DEBUG_FONT_SCALE * screen_height) as u16;
+    let mut y = HUD_FONT_SCALE * screen_height * MENU_LINE_SPACING;
+
+    for line in lines {
+        let text_dimensions = measure_text(line.as_str(), Some(*FONT), font_size, 1.0);
+        y += text_dimensions.height * MENU_LINE_SPACING;
+        draw_text_ex(
+            line.as_str(),
+            0.0,
+            y,
+            TextParams {
+                color: DEBUG_OVERLAY_FG_COLOR,
+                font_size,
+                font: *FONT,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// `position` is relative to the screen, `(0.0, 0.0)` being the top left corner.
+pub fn show_debug_marker(position: (f32, f32), label: &str) {
+    let screen_width = screen_width();
+    let screen_height = screen_height();
+    let font_size = (DEBUG_FONT_SCALE * screen_height) as u16;
+    let x = position.0 * screen_width;
+    let y = position.1 * screen_height;
+
+    draw_circle_lines(
+        x,
+        y,
+        DEBUG_MARKER_RADIUS,
+        DEBUG_MARKER_THICKNESS,
+        DEBUG_OVERLAY_FG_COLOR,
+    );
+    draw_text_ex(
+        label,
+        x + DEBUG_MARKER_RADIUS * 2.0,
+        y,
+        TextParams {
+            color: DEBUG_OVERLAY_FG_COLOR,
+            font_size,
+            font: *FONT,
+            ..Default::default()
+        },
+    );
+}